@@ -0,0 +1,17 @@
+use std::convert::TryFrom;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reefgame::{parse_pattern, Board, Color};
+
+/// Placements suggested for a pattern on a board half covered in stacks,
+/// probing every pair of placements of the two pieces.
+fn completions(c: &mut Criterion) {
+    let board = Board::try_from("r2i1 g2j1 r3k1 b2l1 y2l2 b1j2 y1k2 r1j3 g1k3").unwrap();
+    let patterns = ["DiagonalStacks(r,g)", "AdjacentColors(r,g)", "Line(r)", "Surround(r,g)", "Shape(r2+@i1,?@j1,?@j2,mirror)"];
+    for notation in patterns.iter() {
+        let pattern = parse_pattern(notation).unwrap();
+        c.bench_function(notation, |b| b.iter(|| pattern.completions(black_box(&board), [Color::Red, Color::Green])));
+    }
+}
+
+criterion_group!(benches, completions);
+criterion_main!(benches);
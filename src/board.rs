@@ -1,7 +1,11 @@
 use std::collections::{BTreeSet, HashMap};
 use std::convert::TryFrom;
-use strum::EnumIter;
-use crate::{Error, Stack, pieces::TierLevel};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use strum::{EnumIter, IntoEnumIterator};
+use crate::{Color, Error, Stack, Tier, pieces::TierLevel};
+use crate::encoding::{header, Decoder};
+use crate::notation::{self, split, tokenize, Diagnostic, Mode, Token};
 
 #[allow(non_camel_case_types)]
 #[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -45,13 +49,13 @@ impl TryFrom<&str> for Position {
             "l2" => Ok(Position::l2),
             "l3" => Ok(Position::l3),
             "l4" => Ok(Position::l4),
-            _ => Err(Error::InvalidNotation),
+            _ => Err(Error::notation(notation, 0)),
         }
     }
 }
 
 impl Position {
-    fn from_coords(x: u8, y: u8) -> Result<Position, Error> {
+    pub(crate) fn from_coords(x: u8, y: u8) -> Result<Position, Error> {
         match x {
             1 => {
                 match y {
@@ -93,7 +97,7 @@ impl Position {
         }
     }
 
-    fn to_coords(&self) -> (u8, u8) {
+    pub(crate) fn to_coords(self) -> (u8, u8) {
         match self {
             Position::i1 => (1, 1),
             Position::i2 => (1, 2),
@@ -114,6 +118,15 @@ impl Position {
         }
     }
 
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+
+    /// Single bit identifying the position in a 16-bit board mask.
+    pub fn bit(self) -> u16 {
+        1 << self as u16
+    }
+
     pub fn up(&self) -> Option<Position> {
         let (x, y) = self.to_coords();
         Position::from_coords(x, y + 1).ok()
@@ -155,13 +168,53 @@ impl Position {
     }
 }
 
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (x, y) = self.to_coords();
+        write!(f, "{}{}", (b'h' + x) as char, y)
+    }
+}
+
 pub type Mask = BTreeSet<Position>;
 pub type MaskSet = BTreeSet<Mask>;
 
+/// Positions of a mask as a 16-bit board mask, see `Position::bit`.
+pub fn mask_bits(mask: &Mask) -> u16 {
+    mask.iter().fold(0, |bits, position| bits | position.bit())
+}
+
+/// Positions of a 16-bit board mask, see `Position::bit`.
+pub fn bits_mask(bits: u16) -> Mask {
+    Position::iter().filter(|position| bits & position.bit() != 0).collect()
+}
+
+/// Tag of a board in the binary encoding.
+const BOARD_TAG: u8 = b'B';
+
+/// Bytes holding a board key, see `Board::key`.
+pub(crate) const KEY_BYTES: usize = 10;
+
+/// Line of file letters below a board grid.
+const FILES: &str = "  i  j  k  l";
+
 /// Player's board where pieces are placed and stacked.
-#[derive(Debug)]
+///
+/// A placement returns the stack it covered, which is all `take_back` needs
+/// to undo it, so the board itself keeps no history. Boards compare equal
+/// when their stacks do, regardless of how they were built.
+///
+/// The colours beneath the top of a stack are known when the stack was built
+/// up by placements or listed in layered notation, see `layers`.
+#[derive(Debug, Clone)]
 pub struct Board {
     slots: HashMap<Position, Stack>,
+    /// Colours beneath the top from the bottom up, for stacks of more than
+    /// one piece whose layers are known.
+    beneath: HashMap<Position, Vec<Color>>,
+    /// Positions of the stacks of every colour as 16-bit masks, in colour order.
+    colors: [u16; 4],
+    /// Positions of the stacks of every tier as 16-bit masks, from the first tier up.
+    tiers: [u16; 4],
 }
 
 impl Board {
@@ -169,6 +222,9 @@ impl Board {
     pub fn new() -> Board {
         Board {
             slots: HashMap::with_capacity(16),
+            beneath: HashMap::new(),
+            colors: [0; 4],
+            tiers: [0; 4],
         }
     }
 
@@ -176,53 +232,408 @@ impl Board {
         self.slots.get(position)
     }
 
+    /// Positions of the stacks with the colour on top, see `Position::bit`.
+    pub fn color_mask(&self, color: Color) -> u16 {
+        self.colors[color as usize]
+    }
+
+    /// Positions of the stacks of the tier, see `Position::bit`.
+    pub fn tier_mask(&self, tier: Tier) -> u16 {
+        self.tiers[tier.level() as usize - 1]
+    }
+
+    /// Positions of all stacks, see `Position::bit`.
+    pub fn occupied(&self) -> u16 {
+        self.colors.iter().fold(0, |bits, mask| bits | mask)
+    }
+
+    /// Total number of pieces placed on the board.
+    pub fn pieces(&self) -> usize {
+        self.slots.values().map(|stack| stack.level() as usize).sum()
+    }
+
+    /// Colours of the stack at a position from the bottom up, if known.
+    ///
+    /// Layers are known for single pieces, for stacks built up by placements
+    /// on known stacks and for stacks listed with all their colours in
+    /// layered notation, but not for stacks given only by their top.
+    pub fn layers(&self, position: &Position) -> Option<Vec<Color>> {
+        let stack = self.slots.get(position)?;
+        let mut layers = match stack.tier {
+            Tier::First => Vec::new(),
+            _ => self.beneath.get(position)?.clone(),
+        };
+        layers.push(stack.color);
+        Some(layers)
+    }
+
+    /// Number of pieces of a colour on the board, if the layers of all
+    /// stacks are known.
+    pub fn pieces_of(&self, color: Color) -> Option<usize> {
+        Position::iter().try_fold(0, |count, position| match self.slots.get(&position) {
+            Some(_) => Some(count + self.layers(&position)?.iter().filter(|layer| **layer == color).count()),
+            None => Some(count),
+        })
+    }
+
+    /// Notation listing every stack with all its colours from the bottom up
+    /// where they are known, e.g. `rgb3j3` for a blue stack on green on red.
+    ///
+    /// Stacks with unknown layers and single pieces are written as in the
+    /// canonical notation. Parsing the notation with `Board::try_from` gives
+    /// back the board with its layers.
+    pub fn to_layered_notation(&self) -> String {
+        let placements: Vec<String> = Position::iter()
+            .filter_map(|position| {
+                let stack = self.slots.get(&position)?;
+                let colors: String = match self.layers(&position) {
+                    Some(layers) => layers.iter().map(|color| color.to_notation()).collect(),
+                    None => stack.color.to_notation(),
+                };
+                Some(format!("{}{}{}", colors, stack.tier, position))
+            })
+            .collect();
+        placements.join(" ")
+    }
+
+    /// Canonical notation listing the stacks in position order.
+    ///
+    /// Parsing the notation with `Board::try_from` gives back an equal board.
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+
+    /// Draw the board as a grid of stacks, starting with the row of rank 4.
+    ///
+    /// Empty positions are left blank. With labels, every row starts with
+    /// its rank and the grid ends with a line of file letters, like the
+    /// layout diagram in the README.
+    pub fn to_grid(&self, labels: bool) -> String {
+        let mut grid = String::new();
+        for rank in (1..=4).rev() {
+            let cells: Vec<String> = (1..=4)
+                .map(|file| {
+                    let position = Position::from_coords(file, rank).expect("position is on the board");
+                    self.slots.get(&position).map_or_else(|| "  ".to_string(), |stack| stack.to_string())
+                })
+                .collect();
+            let row = if labels {
+                format!("{} {}", rank, cells.join(" "))
+            } else {
+                cells.join(" ")
+            };
+            grid.push_str(row.trim_end());
+            grid.push('\n');
+        }
+        if labels {
+            grid.push_str(FILES);
+            grid.push('\n');
+        }
+        grid
+    }
+
+    /// Parse a board drawn as a grid of stacks, such as the one produced by
+    /// `to_grid`.
+    ///
+    /// Every row lists the stacks on files `i` through `l` separated by a
+    /// space, with two spaces for an empty position, and trailing empty
+    /// positions may be left out. Rows may start with their rank, in which
+    /// case they may also be indented, and the grid may end with a line of
+    /// file letters. A blank first and last line are ignored, which allows
+    /// a grid to be written on lines of its own within a string literal.
+    pub fn from_grid(grid: &str) -> Result<Board, Error> {
+        let mut rows: Vec<(usize, &str)> = split(grid, '\n', 0)
+            .into_iter()
+            .map(|(offset, row)| (offset, row.strip_suffix('\r').unwrap_or(row)))
+            .collect();
+        if rows.len() > 4 && rows[rows.len() - 1].1.trim().is_empty() {
+            rows.pop();
+        }
+        if rows.len() > 4 && rows[0].1.trim().is_empty() {
+            rows.remove(0);
+        }
+        if rows.len() == 5 {
+            let (offset, files) = rows.pop().expect("grid has five rows");
+            if files.trim() != FILES.trim() {
+                return Err(Error::notation(files, offset));
+            }
+        }
+        if rows.len() != 4 {
+            return Err(Error::notation(grid, 0));
+        }
+        let mut board = Board::new();
+        for ((offset, row), rank) in rows.into_iter().zip((1..=4).rev()) {
+            let (offset, cells) = match row.trim_start() {
+                labelled if labelled.starts_with(|letter: char| letter.is_ascii_digit()) => {
+                    let offset = offset + row.len() - labelled.len();
+                    if labelled[..1] != rank.to_string() {
+                        return Err(Error::notation(&labelled[..1], offset));
+                    }
+                    match &labelled[1..] {
+                        "" => (offset + 1, ""),
+                        cells if cells.starts_with(' ') => (offset + 2, &cells[1..]),
+                        cells => return Err(Error::notation(cells, offset + 1)),
+                    }
+                },
+                _ => (offset, row),
+            };
+            if !cells.is_ascii() {
+                return Err(Error::notation(cells, offset));
+            }
+            if cells.len() > 11 {
+                return Err(Error::notation(&cells[11..], offset + 11));
+            }
+            for (file, start) in (0..cells.len()).step_by(3).enumerate() {
+                if start > 0 && cells.as_bytes()[start - 1] != b' ' {
+                    return Err(Error::notation(&cells[start - 1..start], offset + start - 1));
+                }
+                let cell = &cells[start..cells.len().min(start + 2)];
+                if cell.trim().is_empty() {
+                    continue;
+                }
+                let stack = Stack::try_from(cell).map_err(|error| error.shifted(offset + start))?;
+                let position = Position::from_coords(file as u8 + 1, rank)?;
+                board.put(position, stack);
+            }
+        }
+        Ok(board)
+    }
+
+    /// Stacks packed into the lowest 80 bits, 5 bits per position in
+    /// position order: the tier in the upper 3 bits, 0 for an empty
+    /// position, and the colour in the lower 2 bits.
+    ///
+    /// Equal boards always have the same key and other boards never do.
+    pub fn key(&self) -> u128 {
+        self.slots.iter().fold(0, |key, (position, stack)| {
+            let bits = stack.tier.level() << 2 | stack.color as u8;
+            key | u128::from(bits) << (5 * *position as u32)
+        })
+    }
+
+    /// Board with the stacks packed in a key.
+    ///
+    /// A malformed key is reported as an encoding error at the byte of the
+    /// little-endian key holding the malformed position.
+    pub fn from_key(key: u128) -> Result<Board, Error> {
+        if key >> (8 * KEY_BYTES) != 0 {
+            return Err(Error::InvalidEncoding(KEY_BYTES));
+        }
+        let mut board = Board::new();
+        for position in Position::iter() {
+            let shift = 5 * position as u32;
+            let bits = (key >> shift) as u8 & 0b1_1111;
+            let tier = match bits >> 2 {
+                0 if bits == 0 => continue,
+                0 => return Err(Error::InvalidEncoding(shift as usize / 8)),
+                1 => Tier::First,
+                2 => Tier::Second,
+                3 => Tier::Third,
+                4 => Tier::Fourth,
+                _ => return Err(Error::InvalidEncoding((shift as usize + 2) / 8)),
+            };
+            let color = Color::iter().nth(bits as usize & 0b11).expect("two bits select a colour");
+            board.put(position, Stack { color, tier });
+        }
+        Ok(board)
+    }
+
+    /// Compact binary encoding: a versioned header followed by the key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = header(BOARD_TAG);
+        bytes.extend_from_slice(&self.key().to_le_bytes()[..KEY_BYTES]);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Board, Error> {
+        let mut decoder = Decoder::new(bytes, BOARD_TAG)?;
+        let offset = decoder.offset();
+        let board = Board::decode_key(decoder.take(KEY_BYTES)?).map_err(|error| error.shifted(offset))?;
+        decoder.finish()?;
+        Ok(board)
+    }
+
+    /// Board with the key stored in little-endian bytes.
+    pub(crate) fn decode_key(bytes: &[u8]) -> Result<Board, Error> {
+        let mut key = [0; 16];
+        key[..KEY_BYTES].copy_from_slice(bytes);
+        Board::from_key(u128::from_le_bytes(key))
+    }
+
     pub fn interpret(notation: &str) -> Result<(Position, Stack), Error> {
-        if notation.len() != 4 {
-            return Err(Error::InvalidNotation);
+        let token = Token { text: notation, span: 0..notation.len() };
+        notation::placement(&token, Mode::Strict).map_err(|diagnostics| diagnostics[0].to_error(notation))
+    }
+
+    /// Parse board notation, see `TryFrom<&str>`, reporting every problem
+    /// found instead of only the first one.
+    pub fn parse(notation: &str, mode: Mode) -> Result<Board, Vec<Diagnostic>> {
+        let (tokens, mut diagnostics) = tokenize(notation, mode);
+        let mut board = Board::new();
+        for token in tokens {
+            match notation::layered_placement(&token, mode) {
+                Ok((position, ..)) if board.slots.contains_key(&position) => {
+                    let message = format!("position {} is taken by more than one stack", position);
+                    diagnostics.push(Diagnostic::new(token.span, message));
+                },
+                Ok((position, stack, beneath)) => {
+                    board.put(position, stack);
+                    if !beneath.is_empty() {
+                        board.beneath.insert(position, beneath);
+                    }
+                },
+                Err(found) => diagnostics.extend(found),
+            }
         }
-        let position = Position::try_from(&notation[2..])?;
-        let stack = Stack::try_from(&notation[0..2])?;
-        Ok((position, stack))
+        if !diagnostics.is_empty() {
+            diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+            return Err(diagnostics);
+        }
+        Ok(board)
     }
 
-    pub fn place(&mut self, position: Position, stack: Stack) -> Result<(), Error> {
+    /// Place a stack one tier above the stack on a position, returning the
+    /// stack it covered.
+    pub fn place(&mut self, position: Position, stack: Stack) -> Result<Option<Stack>, Error> {
         if stack.tier.is_on_top_of(&self.slots.get(&position)) {
-            self.slots.insert(position, stack);
-            Ok(())
+            Ok(self.cover(position, stack))
         } else {
-            Err(Error::IllegalMove)
+            Err(Error::IllegalPlacement {
+                position,
+                current: self.slots.get(&position).copied(),
+                attempted: stack,
+            })
         }
     }
 
+    /// Take back the last placement on a position, restoring the stack it
+    /// covered as returned by `place`, and return the removed stack.
+    ///
+    /// Placements have to be taken back in the reverse order they were made.
+    pub fn take_back(&mut self, position: Position, covered: Option<Stack>) -> Option<Stack> {
+        match covered {
+            Some(previous) => {
+                if let Some(beneath) = self.beneath.get_mut(&position) {
+                    beneath.pop();
+                }
+                if previous.tier == Tier::First {
+                    self.beneath.remove(&position);
+                }
+                self.put(position, previous)
+            },
+            None => self.remove(position),
+        }
+    }
+
+    /// Put a stack on top of a position, keeping track of the layers beneath.
+    fn cover(&mut self, position: Position, stack: Stack) -> Option<Stack> {
+        match self.layers(&position) {
+            Some(layers) => self.beneath.insert(position, layers),
+            None => self.beneath.remove(&position),
+        };
+        self.put(position, stack)
+    }
+
+    /// Copy of the stacks of the board without the layers beneath them, for
+    /// trying out placements with `put` and `remove` without keeping track
+    /// of the layers.
+    pub(crate) fn probe(&self) -> Board {
+        Board {
+            slots: self.slots.clone(),
+            beneath: HashMap::new(),
+            colors: self.colors,
+            tiers: self.tiers,
+        }
+    }
+
+    /// Positions of the stacks of every colour and every tier, which tell
+    /// the stacks of the board apart.
+    pub(crate) fn masks(&self) -> ([u16; 4], [u16; 4]) {
+        (self.colors, self.tiers)
+    }
+
+    /// Put a stack at a position, replacing the stack there if any.
+    pub(crate) fn put(&mut self, position: Position, stack: Stack) -> Option<Stack> {
+        let previous = self.remove(position);
+        self.colors[stack.color as usize] |= position.bit();
+        self.tiers[stack.tier.level() as usize - 1] |= position.bit();
+        self.slots.insert(position, stack);
+        previous
+    }
+
+    /// Remove the stack at a position, if any.
+    pub(crate) fn remove(&mut self, position: Position) -> Option<Stack> {
+        let stack = self.slots.remove(&position)?;
+        self.colors[stack.color as usize] &= !position.bit();
+        self.tiers[stack.tier.level() as usize - 1] &= !position.bit();
+        Some(stack)
+    }
+
+    /// Stack created by placing a piece of the given colour, if there is room.
+    pub fn placement(&self, position: Position, color: Color) -> Option<Stack> {
+        let tier = match self.slots.get(&position) {
+            Some(stack) => stack.tier.next()?,
+            None => Tier::First,
+        };
+        Some(Stack { color, tier })
+    }
+
     pub fn play(&mut self, notation: &str) -> Result<(), Error> {
         let (position, stack) = Board::interpret(notation)?;
-        self.place(position, stack)
+        self.place(position, stack)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for position in Position::iter() {
+            if let Some(stack) = self.slots.get(&position) {
+                if !first {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}{}", stack, position)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.slots == other.slots
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
     }
 }
 
 impl TryFrom<&str> for Board {
     type Error = Error;
 
-    fn try_from(notations: &str) -> Result<Self, Self::Error> {
-        let mut board = Board::new();
-        notations
-            .split(" ")
-            .try_fold(&mut board, |board, notation: &str| {
-                let (position, stack) = Board::interpret(notation)?;
-                if board.slots.contains_key(&position) {
-                    Err(Error::InvalidNotation)
-                } else {
-                    board.slots.insert(position, stack);
-                    Ok(board)
-                }
-            })?;
-        Ok(board)
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        Board::parse(notation, Mode::Strict).map_err(|diagnostics| diagnostics[0].to_error(notation))
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::{Pattern, Rng};
+    use crate::encoding::HEADER_BYTES;
 
     #[test]
     fn position_notation() -> Result<(), Error> {
@@ -295,6 +706,63 @@ mod tests {
         Board::try_from("r3i1 y4k3")?;
         assert!(Board::try_from("r3i1 r3i1").is_err());
         assert!(Board::try_from("r3i1 y4k3 ").is_err());
+        assert!(matches!(Board::try_from("r3i1 y4k3 r3i1").unwrap_err(), Error::InvalidNotation { token, offset: 10 } if token == "r3i1"));
+        assert!(matches!(Board::try_from("r3i1 y4k3 r3x1").unwrap_err(), Error::InvalidNotation { token, offset: 12 } if token == "x1"));
+        assert!(matches!(Board::try_from("r3i1 y5k3").unwrap_err(), Error::InvalidNotation { token, offset: 6 } if token == "5"));
+        Ok(())
+    }
+
+    #[test]
+    fn board_parse() -> Result<(), Error> {
+        let board = Board::try_from("b1j2 r1j3 y1k2 g1k3")?;
+        assert_eq!(Board::parse("b1j2 r1j3 y1k2 g1k3", Mode::Strict), Ok(board.clone()));
+        assert_eq!(Board::parse(" V1J2, r1j3,\tY1k2\ng1k3 ", Mode::Lenient), Ok(board));
+        let diagnostics = Board::parse("r3i1  x1j2 r5z9 g1i1 \t", Mode::Strict).unwrap_err();
+        let spans: Vec<std::ops::Range<usize>> = diagnostics.iter().map(|diagnostic| diagnostic.span.clone()).collect();
+        assert_eq!(spans, vec![5..5, 6..7, 12..13, 13..15, 16..20, 21..22]);
+        assert_eq!(diagnostics[4].message, "position i1 is taken by more than one stack");
+        Ok(())
+    }
+
+    #[test]
+    fn position_to_notation() -> Result<(), Error> {
+        for position in Position::iter() {
+            assert_eq!(Position::try_from(position.to_notation().as_str())?, position);
+        }
+        assert_eq!(Position::j4.to_string(), "j4");
+        Ok(())
+    }
+
+    #[test]
+    fn board_to_notation() -> Result<(), Error> {
+        assert_eq!(Board::try_from("y4k3 r3i1")?.to_notation(), "r3i1 y4k3");
+        assert_eq!(Board::new().to_notation(), "");
+        assert_eq!(Board::try_from("")?, Board::new());
+        Ok(())
+    }
+
+    /// Board built by a seeded sequence of random legal placements.
+    pub(crate) fn random_board(seed: u64) -> Result<Board, Error> {
+        let mut rng = Rng::new(seed);
+        let mut board = Board::new();
+        for _ in 0..rng.below(40) {
+            let position = Position::iter().nth(rng.below(16) as usize).unwrap();
+            let color = Color::iter().nth(rng.below(4) as usize).unwrap();
+            if let Some(stack) = board.placement(position, color) {
+                board.place(position, stack)?;
+            }
+        }
+        Ok(board)
+    }
+
+    #[test]
+    fn board_notation_round_trip() -> Result<(), Error> {
+        for seed in 0..200 {
+            let board = random_board(seed)?;
+            let notation = board.to_notation();
+            assert_eq!(Board::try_from(notation.as_str())?, board);
+            assert_eq!(Board::try_from(notation.as_str())?.to_notation(), notation);
+        }
         Ok(())
     }
 
@@ -305,7 +773,200 @@ mod tests {
         board.play("r2j3")?;
         board.play("r3j3")?;
         board.play("b4j3")?;
-        assert!(board.play("g3l4").is_err());
+        let (current, attempted) = (Stack::try_from("y1")?, Stack::try_from("g3")?);
+        assert!(matches!(board.play("g3l4").unwrap_err(), Error::IllegalPlacement {
+            position: Position::l4,
+            current: Some(found),
+            attempted: stack,
+        } if found == current && stack == attempted));
+        Ok(())
+    }
+
+    #[test]
+    fn board_take_back() -> Result<(), Error> {
+        let start = Board::try_from("r1j3 g1k4")?;
+        let mut board = start.clone();
+        let placements = ["b2j3", "y1i1", "r3j3"];
+        let mut covered = Vec::new();
+        for placement in placements.iter() {
+            let (position, stack) = Board::interpret(placement)?;
+            covered.push((position, board.place(position, stack)?));
+        }
+        assert_eq!(covered[0], (Position::j3, Some(Stack::try_from("r1")?)));
+        assert_eq!(covered[1], (Position::i1, None));
+        let placed = board.clone();
+        let (position, stack) = covered.pop().unwrap();
+        assert_eq!(board.take_back(position, stack), Some(Stack::try_from("r3")?));
+        assert_eq!(board.get(&Position::j3), Some(&Stack::try_from("b2")?));
+        for (position, stack) in covered.into_iter().rev() {
+            board.take_back(position, stack);
+        }
+        assert_eq!(board, start);
+        assert_eq!(board.to_layered_notation(), "r1j3 g1k4");
+        for placement in placements.iter() {
+            board.play(placement)?;
+        }
+        assert_eq!(board, placed);
+        Ok(())
+    }
+
+    #[test]
+    fn board_masks() -> Result<(), Error> {
+        let mut board = Board::try_from("r1i1 b4j2 r2k1")?;
+        assert_eq!(board.color_mask(Color::Red), Position::i1.bit() | Position::k1.bit());
+        assert_eq!(board.tier_mask(Tier::Fourth), Position::j2.bit());
+        assert_eq!(board.occupied(), Position::i1.bit() | Position::j2.bit() | Position::k1.bit());
+        let covered = board.place(Position::i1, Stack::try_from("g2")?)?;
+        assert_eq!(board.color_mask(Color::Red), Position::k1.bit());
+        assert_eq!(board.tier_mask(Tier::Second), Position::i1.bit() | Position::k1.bit());
+        board.take_back(Position::i1, covered);
+        assert_eq!(board.color_mask(Color::Green), 0);
+        assert_eq!(bits_mask(board.tier_mask(Tier::First)), btreeset!{Position::i1});
+        for seed in 0..50 {
+            let board = random_board(seed)?;
+            for color in Color::iter() {
+                assert_eq!(bits_mask(board.color_mask(color)), color.fit(&board).into_iter().flatten().collect());
+            }
+            assert_eq!(mask_bits(&bits_mask(board.occupied())), board.occupied());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn board_layers() -> Result<(), Error> {
+        let mut board = Board::try_from("r1j3 b4j2 rgy3k1")?;
+        assert_eq!(board.layers(&Position::j3), Some(vec![Color::Red]));
+        assert_eq!(board.layers(&Position::j2), None);
+        assert_eq!(board.layers(&Position::k1), Some(vec![Color::Red, Color::Green, Color::Yellow]));
+        assert_eq!(board.layers(&Position::i1), None);
+        assert_eq!(board, Board::try_from("r1j3 b4j2 y3k1")?);
+        assert_eq!(board.pieces_of(Color::Red), None);
+        let green = board.place(Position::j3, Stack::try_from("g2")?)?;
+        let blue = board.place(Position::j3, Stack::try_from("b3")?)?;
+        let red = board.place(Position::k1, Stack::try_from("r4")?)?;
+        assert_eq!(board.layers(&Position::j3), Some(vec![Color::Red, Color::Green, Color::Blue]));
+        assert_eq!(board.to_layered_notation(), "b4j2 rgb3j3 rgyr4k1");
+        assert_eq!(board.to_notation(), "b4j2 b3j3 r4k1");
+        board.take_back(Position::k1, red);
+        board.take_back(Position::j3, blue);
+        assert_eq!(board.layers(&Position::j3), Some(vec![Color::Red, Color::Green]));
+        assert_eq!(board.layers(&Position::k1), Some(vec![Color::Red, Color::Green, Color::Yellow]));
+        board.take_back(Position::j3, green);
+        assert_eq!(board.to_layered_notation(), "b4j2 r1j3 rgy3k1");
+        board.play("g2j3")?;
+        assert_eq!(board.layers(&Position::j3), Some(vec![Color::Red, Color::Green]));
+        let board = Board::try_from(Board::try_from("b4j2 rgb3j3 rgyr4k1")?.to_layered_notation().as_str())?;
+        assert_eq!(board.layers(&Position::k1), Some(vec![Color::Red, Color::Green, Color::Yellow, Color::Red]));
+        let board = Board::try_from("rrgb4j2 y1i1")?;
+        assert_eq!(board.pieces_of(Color::Red), Some(2));
+        assert_eq!(board.pieces_of(Color::Yellow), Some(1));
+        assert!(matches!(Board::try_from("r1i1 rg3j2").unwrap_err(), Error::InvalidNotation { token, offset: 5 } if token == "rg"));
+        Ok(())
+    }
+
+    #[test]
+    fn board_placement() -> Result<(), Error> {
+        let board = Board::try_from("r1j3 b4j2")?;
+        assert_eq!(board.placement(Position::i1, Color::Green), Some(Stack::try_from("g1")?));
+        assert_eq!(board.placement(Position::j3, Color::Yellow), Some(Stack::try_from("y2")?));
+        assert_eq!(board.placement(Position::j2, Color::Red), None);
+        Ok(())
+    }
+
+    #[test]
+    fn board_grid() -> Result<(), Error> {
+        let board = Board::try_from("r2i2 r2i3 r1i4 b3j1 r4j2 r3j3 g1j4 b2k1 b3k2 r1k3 r1k4 b2l1")?;
+        let grid = "r1 g1 r1\nr2 r3 r1\nr2 r4 b3\n   b3 b2 b2\n";
+        assert_eq!(board.to_grid(false), grid);
+        assert_eq!(Board::from_grid(grid)?, board);
+        let labelled = "4 r1 g1 r1\n3 r2 r3 r1\n2 r2 r4 b3\n1    b3 b2 b2\n  i  j  k  l\n";
+        assert_eq!(board.to_grid(true), labelled);
+        assert_eq!(Board::from_grid(labelled)?, board);
+        assert_eq!(Board::from_grid("
+            4 r1 g1 r1
+            3 r2 r3 r1
+            2 r2 r4 b3
+            1    b3 b2 b2
+              i  j  k  l
+        ")?, board);
+        assert_eq!(Board::from_grid("\n\n\n\n")?, Board::new());
+        assert_eq!(Board::new().to_grid(true), "4\n3\n2\n1\n  i  j  k  l\n");
+        assert_eq!(Board::from_grid(&Board::new().to_grid(true))?, Board::new());
+        Ok(())
+    }
+
+    #[test]
+    fn board_grid_diagrams() -> Result<(), Error> {
+        // diagrams drawn in comments of the pattern tests
+        let board = Board::try_from("g1i1 b2i2 r2i3 b3j1 g4j2 b3j3 g1j4 y2k1 b3k2 y1k4 r2l2 y2l4")?;
+        assert_eq!(Board::from_grid("   g1 y1 y2\nr2 b3\nb2 g4 b3 r2\ng1 b3 y2\n")?, board);
+        assert_eq!(board.to_grid(false), "   g1 y1 y2\nr2 b3\nb2 g4 b3 r2\ng1 b3 y2\n");
+        let board = Board::try_from("y4i1 g2i2 g2i3 r2i4 r3j1 b4j2 g2j3 r2j4 b2k1 g3k2 r1k3 r1k4 y3l1 y4l3 r3l4")?;
+        let grid = "4 r2 r2 r1 r3\n3 g2 g2 r1 y4\n2 g2 b4 g3\n1 y4 r3 b2 y3\n  i  j  k  l\n";
+        assert_eq!(Board::from_grid(grid)?, board);
+        assert_eq!(board.to_grid(true), grid);
+        Ok(())
+    }
+
+    #[test]
+    fn board_grid_round_trip() -> Result<(), Error> {
+        for seed in 0..200 {
+            let board = random_board(seed)?;
+            assert_eq!(Board::from_grid(&board.to_grid(false))?, board);
+            assert_eq!(Board::from_grid(&board.to_grid(true))?, board);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn board_grid_errors() {
+        let errors = [
+            ("r1\nr1", "r1\nr1", 0),
+            ("r1 x1\n\n\n", "x", 3),
+            ("r1 g1 b1 y1 r1\n\n\n", " r1", 11),
+            ("r1,g1\n\n\n", ",", 2),
+            ("4 r1\n2\n2\n1", "2", 5),
+            ("4r1\n3\n2\n1", "r1", 1),
+            ("4\n3\n2\n1\n  a  b  c  d", "  a  b  c  d", 8),
+            ("r5\n\n\n", "5", 1),
+        ];
+        for (grid, token, offset) in errors.iter() {
+            assert!(matches!(Board::from_grid(grid).unwrap_err(), Error::InvalidNotation { token: found, offset: at } if found == *token && at == *offset), "{}", grid);
+        }
+    }
+
+    #[test]
+    fn board_key() -> Result<(), Error> {
+        let board = Board::try_from("r1i1 g4j1 y2l4")?;
+        assert_eq!(board.key(), 0b00100 | 0b10001 << 20 | 0b01011 << 75);
+        assert_eq!(Board::new().key(), 0);
+        let mut keys = HashMap::new();
+        for seed in 0..200 {
+            let board = random_board(seed)?;
+            assert_eq!(Board::from_key(board.key())?, board);
+            assert_eq!(*keys.entry(board.key()).or_insert_with(|| board.clone()), board);
+        }
+        assert!(matches!(Board::from_key(0b11100 << 5).unwrap_err(), Error::InvalidEncoding(0)));
+        assert!(matches!(Board::from_key(0b00011 << 75).unwrap_err(), Error::InvalidEncoding(9)));
+        assert!(matches!(Board::from_key(1 << 80).unwrap_err(), Error::InvalidEncoding(10)));
+        Ok(())
+    }
+
+    #[test]
+    fn board_bytes() -> Result<(), Error> {
+        let board = Board::try_from("b1j2 r1j3 y1k2 g1k3")?;
+        let bytes = board.to_bytes();
+        let length = HEADER_BYTES + KEY_BYTES;
+        assert_eq!(bytes.len(), length);
+        assert_eq!(&bytes[..HEADER_BYTES], &[b'B', crate::ENCODING_VERSION]);
+        assert_eq!(Board::from_bytes(&bytes)?, board);
+        assert!(matches!(Board::from_bytes(&bytes[..length - 1]).unwrap_err(), Error::InvalidEncoding(at) if at == length - 1));
+        assert!(matches!(Board::from_bytes(&[bytes.clone(), vec![0]].concat()).unwrap_err(), Error::InvalidEncoding(at) if at == length));
+        assert!(matches!(Board::from_bytes(&[b'G', 1]).unwrap_err(), Error::InvalidEncoding(0)));
+        assert!(matches!(Board::from_bytes(&[b'B', 0]).unwrap_err(), Error::InvalidEncoding(1)));
+        let mut invalid = bytes;
+        invalid[length - 1] = 0xff;
+        assert!(matches!(Board::from_bytes(&invalid).unwrap_err(), Error::InvalidEncoding(at) if at == length - 1));
         Ok(())
     }
 }
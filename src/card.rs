@@ -1,25 +1,501 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
-use crate::{Board, Color, Error};
-use crate::pattern::{AdjacentColors, AdjacentT2, Corner, DiagonalStacks, Diagonal, Line, Pattern, Square, Surround};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use crate::{Board, Color, Error, Stack, Tier};
+use crate::notation::{self, words, Diagnostic, Mode};
+use crate::pattern::{canonical_pattern, parse_pattern, AdjacentColors, AdjacentT2, Completion, Corner, DiagonalStacks, Diagonal, Line, Pattern, Square, Surround};
 
-struct Card {
-    pattern: Box<dyn Pattern>,
+/// Card with a scoring pattern and the two pieces it lets the player place.
+#[derive(Clone)]
+pub struct Card {
+    id: u8,
+    pattern: Arc<dyn Pattern + Send + Sync>,
     value: u32,
     pieces: [Color; 2],
 }
 
 impl Card {
-    fn score(&self, board: &Board) -> u32 {
-        0
+    pub fn new(id: u8, pattern: Arc<dyn Pattern + Send + Sync>, value: u32, pieces: [Color; 2]) -> Card {
+        Card {
+            id,
+            pattern,
+            value,
+            pieces,
+        }
     }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Short reference to the card by its identifier, e.g. `c17`.
+    pub fn reference(&self) -> String {
+        format!("c{}", self.id)
+    }
+
+    /// Parse a card reference such as `c17` into the card identifier.
+    pub fn parse_reference(notation: &str) -> Result<u8, Error> {
+        let digits = notation.strip_prefix('c').ok_or_else(|| Error::notation(notation, 0))?;
+        if digits.is_empty() || digits.starts_with('0') || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return Err(Error::notation(notation, 0));
+        }
+        digits.parse().map_err(|_| Error::notation(notation, 0))
+    }
+
+    pub fn pattern(&self) -> &dyn Pattern {
+        self.pattern.as_ref()
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn pieces(&self) -> [Color; 2] {
+        self.pieces
+    }
+
+    /// Notation of the whole card, see `TryFrom<&str>`.
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+
+    /// Score the card's pattern on a board.
+    ///
+    /// Every piece may be counted only once per card, so for most patterns
+    /// the score is the largest number of non-overlapping pattern fits times
+    /// the card value. See `Pattern::count`.
+    pub fn score(&self, board: &Board) -> u32 {
+        self.pattern.count(board) as u32 * self.value
+    }
+
+    /// Placements of the card's pieces that would raise its score, with the
+    /// points gained, best first. See `Pattern::completions`.
+    pub fn completions(&self, board: &Board) -> Vec<Completion> {
+        let mut completions = self.pattern.completions(board, self.pieces);
+        for completion in completions.iter_mut() {
+            completion.gain *= self.value;
+        }
+        completions
+    }
+}
+
+/// Reference, pattern, value and pieces, e.g. `c17 Diagonal(b) 2 bg`.
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}{}",
+            self.reference(),
+            self.pattern.to_notation(),
+            self.value,
+            self.pieces[0],
+            self.pieces[1],
+        )
+    }
+}
+
+impl TryFrom<&str> for Card {
+    type Error = Error;
+
+    /// Parse a card, which need not be part of the placeholder deck.
+    ///
+    /// The notation lists the card reference, its pattern as accepted by
+    /// `parse_pattern`, its value and its two pieces, separated by spaces.
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        Card::parse(notation, Mode::Strict).map_err(|diagnostics| diagnostics[0].to_error(notation))
+    }
+}
+
+impl Card {
+    /// Parse the notation of a card, see `TryFrom<&str>`, reporting every
+    /// problem found instead of only the first one.
+    ///
+    /// In lenient mode, the pattern name may be written in any case, and
+    /// the fields may be separated by any whitespace, though not by commas,
+    /// which separate the pattern arguments.
+    pub fn parse(notation: &str, mode: Mode) -> Result<Card, Vec<Diagnostic>> {
+        let (tokens, diagnostics) = words(notation, mode);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        let [reference, pattern, value, pieces] = match &tokens[..] {
+            [reference, pattern, value, pieces] => [reference, pattern, value, pieces],
+            _ => {
+                let message = "expected a reference, pattern, value and pieces such as c17 Diagonal(b) 2 bg";
+                return Err(vec![Diagnostic::new(0..notation.len(), message.to_string())]);
+            },
+        };
+        let id = notation::reference(reference, mode).map_err(|diagnostic| vec![diagnostic]);
+        let pattern = parse_pattern(&canonical_pattern(pattern.text, mode)).map_err(|error| {
+            let message = format!("expected a pattern such as Line(r) or Surround(y,b), found '{}'", pattern.text);
+            vec![Diagnostic::from_error(&error.shifted(pattern.span.start), message)]
+        });
+        let value = Some(value.text)
+            .filter(|value| !value.is_empty() && value.bytes().all(|digit| digit.is_ascii_digit()))
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| vec![Diagnostic::new(value.span.clone(), format!("expected a point value, found '{}'", value.text))]);
+        let pieces = notation::pieces(pieces, mode);
+        match (id, pattern, value, pieces) {
+            (Ok(id), Ok(pattern), Ok(value), Ok(pieces)) => Ok(Card::new(id, pattern, value, pieces)),
+            (id, pattern, value, pieces) => Err(id.err().into_iter()
+                .chain(pattern.err())
+                .chain(value.err())
+                .chain(pieces.err())
+                .flatten()
+                .collect()),
+        }
+    }
+}
+
+impl fmt::Debug for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Card")
+            .field("id", &self.id)
+            .field("value", &self.value)
+            .field("pieces", &self.pieces)
+            .finish()
+    }
+}
+
+impl PartialEq for Card {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.value == other.value
+            && self.pieces == other.pieces
+            && self.pattern.to_notation() == other.pattern.to_notation()
+    }
+}
+
+impl Eq for Card {}
+
+/// Made-up deck of 48 cards built from the available patterns, spread evenly
+/// over the colours, ordered by identifier starting at 1.
+///
+/// This is not the card list of the published game and may change once the
+/// real one is available, so its identifiers only name cards within this
+/// deck. Games with the real cards should be set up from a deck file, see
+/// `parse_deck` and `Game::with_display`.
+pub fn placeholder_deck() -> Vec<Card> {
+    deck().to_vec()
+}
+
+/// Cards of the placeholder deck, built on first use.
+fn deck() -> &'static [Card] {
+    static DECK: OnceLock<Vec<Card>> = OnceLock::new();
+    DECK.get_or_init(build_placeholder_deck)
+}
+
+fn build_placeholder_deck() -> Vec<Card> {
+    use Color::*;
+    let fourth = |color| Stack { color, tier: Tier::Fourth };
+    let cards: Vec<(Arc<dyn Pattern + Send + Sync>, u32, [Color; 2])> = vec![
+        (Arc::new(Line(Red)), 2, [Red, Green]),
+        (Arc::new(Diagonal(Red)), 2, [Red, Yellow]),
+        (Arc::new(Corner(Red)), 2, [Red, Red]),
+        (Arc::new(Square(Red)), 3, [Green, Green]),
+        (Arc::new(AdjacentT2(Red)), 2, [Red, Yellow]),
+        (Arc::new(fourth(Red)), 3, [Red, Green]),
+        (Arc::new(Line(Green)), 2, [Green, Blue]),
+        (Arc::new(Diagonal(Green)), 2, [Green, Red]),
+        (Arc::new(Corner(Green)), 2, [Green, Green]),
+        (Arc::new(Square(Green)), 3, [Blue, Blue]),
+        (Arc::new(AdjacentT2(Green)), 2, [Green, Red]),
+        (Arc::new(fourth(Green)), 3, [Green, Blue]),
+        (Arc::new(Line(Blue)), 2, [Blue, Yellow]),
+        (Arc::new(Diagonal(Blue)), 2, [Blue, Green]),
+        (Arc::new(Corner(Blue)), 2, [Blue, Blue]),
+        (Arc::new(Square(Blue)), 3, [Yellow, Yellow]),
+        (Arc::new(AdjacentT2(Blue)), 2, [Blue, Green]),
+        (Arc::new(fourth(Blue)), 3, [Blue, Yellow]),
+        (Arc::new(Line(Yellow)), 2, [Yellow, Red]),
+        (Arc::new(Diagonal(Yellow)), 2, [Yellow, Blue]),
+        (Arc::new(Corner(Yellow)), 2, [Yellow, Yellow]),
+        (Arc::new(Square(Yellow)), 3, [Red, Red]),
+        (Arc::new(AdjacentT2(Yellow)), 2, [Yellow, Blue]),
+        (Arc::new(fourth(Yellow)), 3, [Yellow, Red]),
+        (Arc::new(AdjacentColors(Red, Green)), 1, [Red, Green]),
+        (Arc::new(AdjacentColors(Red, Blue)), 1, [Red, Blue]),
+        (Arc::new(AdjacentColors(Red, Yellow)), 1, [Red, Yellow]),
+        (Arc::new(AdjacentColors(Green, Blue)), 1, [Green, Blue]),
+        (Arc::new(AdjacentColors(Green, Yellow)), 1, [Green, Yellow]),
+        (Arc::new(AdjacentColors(Blue, Yellow)), 1, [Blue, Yellow]),
+        (Arc::new(DiagonalStacks(Red, Green)), 2, [Green, Red]),
+        (Arc::new(DiagonalStacks(Red, Blue)), 2, [Blue, Red]),
+        (Arc::new(DiagonalStacks(Red, Yellow)), 2, [Yellow, Red]),
+        (Arc::new(DiagonalStacks(Green, Blue)), 2, [Blue, Green]),
+        (Arc::new(DiagonalStacks(Green, Yellow)), 2, [Yellow, Green]),
+        (Arc::new(DiagonalStacks(Blue, Yellow)), 2, [Yellow, Blue]),
+        (Arc::new(Surround(Red, Green)), 1, [Green, Green]),
+        (Arc::new(Surround(Red, Blue)), 1, [Blue, Blue]),
+        (Arc::new(Surround(Red, Yellow)), 1, [Yellow, Yellow]),
+        (Arc::new(Surround(Green, Red)), 1, [Red, Red]),
+        (Arc::new(Surround(Green, Blue)), 1, [Blue, Blue]),
+        (Arc::new(Surround(Green, Yellow)), 1, [Yellow, Yellow]),
+        (Arc::new(Surround(Blue, Red)), 1, [Red, Red]),
+        (Arc::new(Surround(Blue, Green)), 1, [Green, Green]),
+        (Arc::new(Surround(Blue, Yellow)), 1, [Yellow, Yellow]),
+        (Arc::new(Surround(Yellow, Red)), 1, [Red, Red]),
+        (Arc::new(Surround(Yellow, Green)), 1, [Green, Green]),
+        (Arc::new(Surround(Yellow, Blue)), 1, [Blue, Blue]),
+    ];
+    cards
+        .into_iter()
+        .enumerate()
+        .map(|(index, (pattern, value, pieces))| Card::new(index as u8 + 1, pattern, value, pieces))
+        .collect()
+}
+
+/// Parse a deck written one card per line, skipping blank lines.
+///
+/// Errors carry the line of the offending card, and card identifiers have
+/// to be unique within the deck.
+pub fn parse_deck(notation: &str) -> Result<Vec<Card>, Error> {
+    let mut cards: Vec<Card> = Vec::new();
+    for (index, line) in notation.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let card = Card::try_from(line).map_err(|error| error.at_line(index + 1))?;
+        if cards.iter().any(|other| other.id == card.id) {
+            return Err(Error::notation(&card.reference(), 0).at_line(index + 1));
+        }
+        cards.push(card);
+    }
+    Ok(cards)
+}
+
+/// Write a deck one card per line, the reverse of `parse_deck`.
+pub fn deck_notation(cards: &[Card]) -> String {
+    cards.iter().map(|card| format!("{}\n", card)).collect()
+}
+
+/// Size of the largest subset of mutually disjoint 16-bit board masks.
+pub(crate) fn disjoint_masks(masks: &[u16]) -> usize {
+    let mut memo = HashMap::new();
+    pack(masks, u16::MAX, &mut memo)
+}
+
+/// Pack as many masks as possible into the `free` cells.
+///
+/// The lowest cell any usable mask covers is either left empty or covered
+/// by exactly one of the masks containing it, which splits the search
+/// without ever trying the same combination twice.
+fn pack(masks: &[u16], free: u16, memo: &mut HashMap<u16, usize>) -> usize {
+    let usable = masks
+        .iter()
+        .filter(|&&mask| mask & !free == 0)
+        .fold(0, |bits, mask| bits | mask);
+    if usable == 0 {
+        return 0;
+    }
+    if let Some(&count) = memo.get(&free) {
+        return count;
+    }
+    let cell = usable & usable.wrapping_neg();
+    let mut best = pack(masks, free & !cell, memo);
+    for &mask in masks.iter().filter(|&&mask| mask & cell != 0 && mask & !free == 0) {
+        best = best.max(1 + pack(masks, free & !mask, memo));
+    }
+    memo.insert(free, best);
+    best
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryFrom;
+    use crate::Error;
+    use crate::board::{mask_bits, MaskSet};
+
+    /// Size of the largest subset of mutually disjoint masks.
+    fn disjoint_fits(fits: &MaskSet) -> usize {
+        let masks: Vec<u16> = fits.iter().map(mask_bits).collect();
+        disjoint_masks(&masks)
+    }
+
+    #[test]
+    fn card_score_line() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4 r1 r1 r1 r1
+            3 r2 r1 r1
+            2
+            1 y1 y1 y1 y1
+        ")?;
+        let card = Card::new(0, Arc::new(Line(Color::Red)), 2, [Color::Red, Color::Red]);
+        assert_eq!(card.score(&board), 4);
+        let card = Card::new(0, Arc::new(Line(Color::Yellow)), 3, [Color::Yellow, Color::Red]);
+        assert_eq!(card.score(&board), 3);
+        let card = Card::new(0, Arc::new(Line(Color::Blue)), 3, [Color::Blue, Color::Red]);
+        assert_eq!(card.score(&board), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn card_score_corner() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4 r1 g1 r1
+            3 r2 r3 r1
+            2 r2 r4 b3
+            1    b3 b2 b2
+        ")?;
+        let card = Card::new(0, Arc::new(Corner(Color::Red)), 3, [Color::Red, Color::Green]);
+        assert_eq!(card.score(&board), 6);
+        let card = Card::new(0, Arc::new(Corner(Color::Blue)), 3, [Color::Blue, Color::Green]);
+        assert_eq!(card.score(&board), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn card_score_square() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4 g1 g1 g1 g1
+            3 g1 g1 g1 g1
+            2 g1 g1 g1
+            1 g1 g1
+        ")?;
+        let card = Card::new(0, Arc::new(Square(Color::Green)), 4, [Color::Green, Color::Green]);
+        assert_eq!(card.score(&board), 12);
+        Ok(())
+    }
+
+    #[test]
+    fn card_score_surround() -> Result<(), Error> {
+        // The rules score every stack of the second colour around the tallest
+        // stack of the first colour, diagonals included, and only one of the
+        // tallest stacks when several tie.
+        let board = Board::from_grid("
+            4 r2 r2 r1 r3
+            3 g2 g2 r1 y4
+            2 g2 b4 g3
+            1 y4 r3 b2 y3
+        ")?;
+        // y4 on l3 is surrounded by r1 on k3, r1 on k4 and r3 on l4, while
+        // the other y4 on i1 only touches r3 on j1
+        let card = Card::new(0, Arc::new(Surround(Color::Yellow, Color::Red)), 1, [Color::Yellow, Color::Red]);
+        assert_eq!(card.score(&board), 3);
+        // r3 on j1 touches g2 on i2 and g3 on k2, r3 on l4 no green
+        let card = Card::new(0, Arc::new(Surround(Color::Red, Color::Green)), 2, [Color::Red, Color::Green]);
+        assert_eq!(card.score(&board), 4);
+        // only the tallest stack counts, even without any neighbour to score
+        let board = Board::try_from("g1i1 r3j1 g1k1 r4l4")?;
+        assert_eq!(card.score(&board), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn card_completions() -> Result<(), Error> {
+        let board = Board::try_from("r1i1 r1j1 r1i2 r1j2")?;
+        let card = Card::new(0, Arc::new(Line(Color::Red)), 2, [Color::Red, Color::Red]);
+        let completions = card.completions(&board);
+        assert_eq!(completions[0].gain, 4);
+        let [(first, a), (second, b)] = completions[0].placements;
+        let mut board = board.clone();
+        board.place(first, a)?;
+        board.place(second, b)?;
+        assert_eq!(card.score(&board), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn placeholder_cards() {
+        let cards = placeholder_deck();
+        assert_eq!(cards.len(), 48);
+        for (index, card) in cards.iter().enumerate() {
+            assert_eq!(card.id() as usize, index + 1);
+        }
+        for color in [Color::Red, Color::Green, Color::Blue, Color::Yellow].iter() {
+            let count = cards
+                .iter()
+                .flat_map(|card| card.pieces().to_vec())
+                .filter(|piece| piece == color)
+                .count();
+            assert_eq!(count, 24);
+        }
+    }
+
+    #[test]
+    fn card_reference() -> Result<(), Error> {
+        assert_eq!(placeholder_deck()[16].reference(), "c17");
+        assert_eq!(Card::parse_reference("c17")?, 17);
+        assert_eq!(Card::parse_reference("c255")?, 255);
+        assert!(Card::parse_reference("c256").is_err());
+        assert!(Card::parse_reference("c07").is_err());
+        assert!(Card::parse_reference("c+7").is_err());
+        assert!(Card::parse_reference("c").is_err());
+        assert!(Card::parse_reference("17").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn disjoint_fits_selection() {
+        use crate::Position::*;
+        assert_eq!(disjoint_fits(&MaskSet::new()), 0);
+        // a greedy pick of the middle mask would only find one fit
+        assert_eq!(disjoint_fits(&btreeset!{
+            btreeset!{i1, j1},
+            btreeset!{j1, k1},
+            btreeset!{k1, l1},
+        }), 2);
+        assert_eq!(disjoint_fits(&btreeset!{
+            btreeset!{i1, i2, i3},
+            btreeset!{i2, i3, i4},
+            btreeset!{i1, j1, k1},
+            btreeset!{i4, j4, k4},
+        }), 2);
+    }
+
+    #[test]
+    fn card_notation() -> Result<(), Error> {
+        let card = placeholder_deck()[16].clone();
+        assert_eq!(card.to_notation(), "c17 AdjacentT2(b) 2 bg");
+        assert_eq!(Card::try_from("c17 AdjacentT2(b) 2 bg")?, card);
+        let custom = Card::try_from("c60 Surround(y,b) 5 ry")?;
+        assert_eq!((custom.id(), custom.value(), custom.pieces()), (60, 5, [Color::Red, Color::Yellow]));
+        assert_eq!(custom.pattern().to_notation(), "Surround(y,b)");
+        assert_ne!(Card::try_from("c17 Line(b) 2 bg")?, card);
+        let errors = [
+            ("c17 Ring(b) 2 bg", "Ring", 4),
+            ("c17 Line(x) 2 bg", "x", 9),
+            ("c17 Line(b) x bg", "x", 12),
+            ("c17 Line(b) 2 bx", "x", 15),
+            ("c17 Line(b) 2 bgr", "bgr", 14),
+            ("c17 Line(b) 2", "c17 Line(b) 2", 0),
+            ("17 Line(b) 2 bg", "17", 0),
+        ];
+        for (notation, token, offset) in errors.iter() {
+            assert!(matches!(Card::try_from(*notation).unwrap_err(), Error::InvalidNotation { token: found, offset: at } if found == *token && at == *offset), "{}", notation);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn card_parse_lenient() -> Result<(), Error> {
+        let card = Card::parse("C60  surround(Y,V)\t5 RY", Mode::Lenient).unwrap();
+        assert_eq!(card, Card::try_from("c60 Surround(y,b) 5 ry")?);
+        assert_eq!(Card::parse("c17 diagonal(v) 2 vg", Mode::Lenient).unwrap().to_notation(), "c17 Diagonal(b) 2 bg");
+        assert!(Card::parse("c17 diagonal(b) 2 bg", Mode::Strict).is_err());
+        let diagnostics = Card::parse("x17 Line(q) 2x bz", Mode::Strict).unwrap_err();
+        let spans: Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.span.clone()).collect();
+        assert_eq!(spans, vec![0..3, 9..10, 12..14, 16..17]);
+        assert_eq!(diagnostics[2].to_string(), "12..14: expected a point value, found '2x'");
+        Ok(())
+    }
 
     #[test]
-    fn card_test() -> Result<(), Error> {
+    fn deck_notation_round_trip() -> Result<(), Error> {
+        let cards = placeholder_deck();
+        let deck = deck_notation(&cards);
+        assert_eq!(deck.lines().count(), 48);
+        assert_eq!(parse_deck(&deck)?, cards);
+        assert_eq!(
+            parse_deck("c1 Line(r) 2 rg\n\nc1 Line(g) 2 gb\n").unwrap_err().to_string(),
+            Error::notation("c1", 0).at_line(3).to_string(),
+        );
+        assert!(matches!(
+            parse_deck("c1 Line(r) 2 rg\nc2 Line(q) 2 gb\n").unwrap_err(),
+            Error::InvalidRecord { line: 2, source } if matches!(*source, Error::InvalidNotation { ref token, offset: 8 } if token == "q")
+        ));
         Ok(())
     }
 }
@@ -0,0 +1,181 @@
+use crate::{Card, Error, Reason};
+
+/// Number of card piles in the central display.
+pub const PILES: usize = 4;
+
+/// Small deterministic pseudo-random generator (SplitMix64).
+///
+/// The sequence depends on nothing but the seed, so a shuffle can be
+/// replayed exactly on any platform and with any version of the crate.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed number in `0..bound`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bound` is zero, since the range is then empty.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "Rng::below needs a bound greater than zero");
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+
+    /// Fisher-Yates shuffle of a slice.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// Central card display made of face-up piles.
+///
+/// Only the top card of every pile is visible. Taking it reveals the card
+/// underneath, until the pile runs out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardDisplay {
+    piles: Vec<Vec<Card>>,
+}
+
+impl CardDisplay {
+    /// Shuffle the cards with the given seed and deal them into the piles.
+    pub fn new(mut cards: Vec<Card>, seed: u64) -> CardDisplay {
+        Rng::new(seed).shuffle(&mut cards);
+        let mut piles = vec![Vec::new(); PILES];
+        for (index, card) in cards.into_iter().enumerate() {
+            piles[index % PILES].push(card);
+        }
+        CardDisplay { piles }
+    }
+
+    /// Create a display from piles listed bottom to top.
+    pub fn from_piles(piles: Vec<Vec<Card>>) -> CardDisplay {
+        CardDisplay { piles }
+    }
+
+    pub fn piles(&self) -> usize {
+        self.piles.len()
+    }
+
+    /// Visible card on top of a pile.
+    pub fn top(&self, pile: usize) -> Option<&Card> {
+        self.piles.get(pile).and_then(|cards| cards.last())
+    }
+
+    /// Cards of a pile from the bottom to the top.
+    pub fn cards(&self, pile: usize) -> &[Card] {
+        self.piles.get(pile).map_or(&[], |cards| cards.as_slice())
+    }
+
+    /// Number of cards left in a pile.
+    pub fn len(&self, pile: usize) -> usize {
+        self.piles.get(pile).map_or(0, |cards| cards.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.piles.iter().all(|cards| cards.is_empty())
+    }
+
+    /// First pile that has run out of cards, if any.
+    pub fn empty_pile(&self) -> Option<usize> {
+        self.piles.iter().position(|cards| cards.is_empty())
+    }
+
+    /// Take the top card of a pile, revealing the next one.
+    pub fn take(&mut self, pile: usize) -> Result<Card, Error> {
+        self.piles
+            .get_mut(pile)
+            .ok_or(Error::rule(Reason::NoSuchPile))?
+            .pop()
+            .ok_or(Error::rule(Reason::PileEmpty))
+    }
+
+    /// Return a taken card on top of its pile.
+    pub(crate) fn put_back(&mut self, pile: usize, card: Card) {
+        self.piles[pile].push(card);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::placeholder_deck;
+
+    fn tops(display: &CardDisplay) -> Vec<Option<u8>> {
+        (0..PILES).map(|pile| display.top(pile).map(|card| card.id())).collect()
+    }
+
+    #[test]
+    fn rng_sequence() {
+        // reference values of SplitMix64 seeded with 1234567
+        let mut rng = Rng::new(1234567);
+        assert_eq!(rng.next_u64(), 6457827717110365317);
+        assert_eq!(rng.next_u64(), 3203168211198807973);
+        let mut rng = Rng::new(42);
+        for bound in 1..100 {
+            assert!(rng.below(bound) < bound);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "bound greater than zero")]
+    fn rng_below_zero() {
+        Rng::new(42).below(0);
+    }
+
+    #[test]
+    fn display_shuffle() {
+        let display = CardDisplay::new(placeholder_deck(), 7);
+        assert_eq!(display, CardDisplay::new(placeholder_deck(), 7));
+        assert_ne!(display, CardDisplay::new(placeholder_deck(), 8));
+        assert_eq!(display.piles(), PILES);
+        assert_eq!((0..PILES).map(|pile| display.len(pile)).sum::<usize>(), 48);
+        let mut ids: Vec<u8> = (0..PILES)
+            .flat_map(|pile| display.piles[pile].iter().map(|card| card.id()))
+            .collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (1..=48).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn display_take() -> Result<(), Error> {
+        let cards = placeholder_deck();
+        let mut display = CardDisplay::from_piles(vec![
+            vec![cards[0].clone(), cards[1].clone()],
+            vec![cards[2].clone()],
+        ]);
+        assert_eq!(tops(&display), vec![Some(2), Some(3), None, None]);
+        assert_eq!(display.empty_pile(), None);
+        assert_eq!(display.take(0)?, cards[1]);
+        assert_eq!(display.top(0), Some(&cards[0]));
+        assert_eq!(display.take(1)?, cards[2]);
+        assert_eq!(display.empty_pile(), Some(1));
+        assert!(display.take(1).is_err());
+        assert!(display.take(2).is_err());
+        display.take(0)?;
+        assert!(display.is_empty());
+        Ok(())
+    }
+}
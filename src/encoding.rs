@@ -0,0 +1,73 @@
+use crate::Error;
+
+/// Version of the binary encoding, stored in the header of every encoding.
+///
+/// It changes whenever the layout does, so data stored by an older version
+/// is rejected instead of being misread.
+pub const ENCODING_VERSION: u8 = 2;
+
+/// Bytes of the header starting every encoding.
+pub(crate) const HEADER_BYTES: usize = 2;
+
+/// Header starting an encoding: the kind of data followed by the version.
+pub(crate) fn header(tag: u8) -> Vec<u8> {
+    vec![tag, ENCODING_VERSION]
+}
+
+/// Cursor reading an encoding byte by byte, reporting the offset of any
+/// malformed or missing byte.
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Start decoding after checking the header.
+    pub(crate) fn new(bytes: &'a [u8], tag: u8) -> Result<Decoder<'a>, Error> {
+        let mut decoder = Decoder { bytes, offset: 0 };
+        if decoder.byte()? != tag {
+            return Err(Error::InvalidEncoding(0));
+        }
+        if decoder.byte()? != ENCODING_VERSION {
+            return Err(Error::InvalidEncoding(1));
+        }
+        Ok(decoder)
+    }
+
+    /// Offset of the next byte to be read.
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.bytes
+            .get(self.offset..self.offset + count)
+            .ok_or(Error::InvalidEncoding(self.bytes.len()))?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Error> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Check that nothing is left after the decoded data.
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        if self.offset != self.bytes.len() {
+            return Err(Error::InvalidEncoding(self.offset));
+        }
+        Ok(())
+    }
+}
@@ -1,20 +1,201 @@
 use std::fmt;
+use std::io;
+use crate::{Position, Stack};
+
+/// Game rule broken by an illegal move.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Reason {
+    HandFull,
+    ReserveFull,
+    /// Reserve doesn't hold the pieces needed.
+    MissingPieces,
+    /// Placed pieces differ from the ones named by the card.
+    WrongPieces,
+    /// Discarded pieces don't match the reserve overflow.
+    WrongDiscard,
+    CardNotInHand,
+    PileEmpty,
+    /// Card is not on top of any display pile.
+    CardNotInDisplay,
+    NoSuchPile,
+    SupplyEmpty,
+    IllegalPlacement,
+    /// Card discarded while a take or a play is still possible.
+    MovesLeft,
+    GameOver,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::HandFull => write!(f, "hand is full"),
+            Reason::ReserveFull => write!(f, "reserve is full"),
+            Reason::MissingPieces => write!(f, "reserve lacks the pieces"),
+            Reason::WrongPieces => write!(f, "wrong pieces for the card"),
+            Reason::WrongDiscard => write!(f, "discarded pieces don't match the overflow"),
+            Reason::CardNotInHand => write!(f, "card is not in hand"),
+            Reason::PileEmpty => write!(f, "pile is empty"),
+            Reason::CardNotInDisplay => write!(f, "card is not on top of a pile"),
+            Reason::NoSuchPile => write!(f, "no such pile"),
+            Reason::SupplyEmpty => write!(f, "supply is out of the piece"),
+            Reason::IllegalPlacement => write!(f, "pieces cannot be placed"),
+            Reason::MovesLeft => write!(f, "a card can still be taken or played"),
+            Reason::GameOver => write!(f, "game is over"),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Error {
-    InvalidNotation,
-    IllegalMove,
+    /// Offending token and its byte offset within the parsed notation.
+    InvalidNotation {
+        token: String,
+        offset: usize,
+    },
+    /// Stack that is not exactly one tier above the one on the position.
+    IllegalPlacement {
+        position: Position,
+        current: Option<Stack>,
+        attempted: Stack,
+    },
+    /// Action breaking a game rule, possibly caused by another error.
+    IllegalMove {
+        reason: Reason,
+        source: Option<Box<Error>>,
+    },
+    /// Error on the given line of a game record, counted from 1.
+    InvalidRecord {
+        line: usize,
+        source: Box<Error>,
+    },
+    /// Byte offset of the first malformed byte of a binary encoding.
+    InvalidEncoding(usize),
+    /// Failure to read or write a game record.
+    Io(io::Error),
     OutOfBounds,
 }
 
-impl std::error::Error for Error {}
+impl Error {
+    pub fn notation(token: &str, offset: usize) -> Error {
+        Error::InvalidNotation {
+            token: token.to_string(),
+            offset,
+        }
+    }
+
+    pub fn rule(reason: Reason) -> Error {
+        Error::IllegalMove {
+            reason,
+            source: None,
+        }
+    }
+
+    /// Move the offset of a notation or encoding error by the position of
+    /// its token or data.
+    pub(crate) fn shifted(self, by: usize) -> Error {
+        match self {
+            Error::InvalidNotation { token, offset } => Error::InvalidNotation {
+                token,
+                offset: offset + by,
+            },
+            Error::InvalidEncoding(offset) => Error::InvalidEncoding(offset + by),
+            error => error,
+        }
+    }
+
+    /// Wrap an error as the cause of a broken rule.
+    pub(crate) fn because(self, reason: Reason) -> Error {
+        Error::IllegalMove {
+            reason,
+            source: Some(Box::new(self)),
+        }
+    }
+
+    /// Attach the line of a game record the error was found on.
+    pub(crate) fn at_line(self, line: usize) -> Error {
+        Error::InvalidRecord {
+            line,
+            source: Box::new(self),
+        }
+    }
+
+    /// Rule broken by an illegal move.
+    pub fn reason(&self) -> Option<Reason> {
+        match self {
+            Error::IllegalMove { reason, .. } => Some(*reason),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IllegalMove { source: Some(source), .. } => Some(source.as_ref()),
+            Error::InvalidRecord { source, .. } => Some(source.as_ref()),
+            Error::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Error::InvalidNotation => write!(f, "Failed to parse notation"),
-            Error::IllegalMove => write!(f, "Illegal player move"),
+        match self {
+            Error::InvalidNotation { token, offset } => {
+                write!(f, "Failed to parse notation '{}' at offset {}", token, offset)
+            },
+            Error::IllegalPlacement { position, current: Some(current), attempted } => {
+                write!(f, "Cannot place {} on {} holding {}", attempted, position, current)
+            },
+            Error::IllegalPlacement { position, current: None, attempted } => {
+                write!(f, "Cannot place {} on empty {}", attempted, position)
+            },
+            Error::IllegalMove { reason, .. } => write!(f, "Illegal player move: {}", reason),
+            Error::InvalidRecord { line, .. } => write!(f, "Invalid record at line {}", line),
+            Error::InvalidEncoding(offset) => write!(f, "Invalid binary encoding at byte {}", offset),
+            Error::Io(error) => write!(f, "I/O error: {}", error),
             Error::OutOfBounds => write!(f, "Index out of bounds"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn error_context() {
+        let placement = Error::IllegalPlacement {
+            position: Position::i1,
+            current: None,
+            attempted: Stack { color: crate::Color::Red, tier: crate::Tier::Second },
+        };
+        assert_eq!(placement.to_string(), "Cannot place r2 on empty i1");
+        let error = placement.because(Reason::IllegalPlacement);
+        assert_eq!(error.reason(), Some(Reason::IllegalPlacement));
+        assert_eq!(error.to_string(), "Illegal player move: pieces cannot be placed");
+        assert_eq!(error.source().map(|source| source.to_string()), Some("Cannot place r2 on empty i1".to_string()));
+        assert!(Error::rule(Reason::HandFull).source().is_none());
+        assert!(matches!(Error::notation("x", 1).shifted(4), Error::InvalidNotation { token, offset: 5 } if token == "x"));
+        assert_eq!(Error::InvalidEncoding(3).shifted(2).to_string(), "Invalid binary encoding at byte 5");
+        let record = Error::rule(Reason::PileEmpty).at_line(7);
+        assert_eq!(record.to_string(), "Invalid record at line 7");
+        assert_eq!(record.source().map(|source| source.to_string()), Some("Illegal player move: pile is empty".to_string()));
+        let io = Error::from(io::Error::new(io::ErrorKind::UnexpectedEof, "record cut short"));
+        assert!(matches!(io, Error::Io(ref error) if error.kind() == io::ErrorKind::UnexpectedEof));
+        assert_eq!(io.to_string(), "I/O error: record cut short");
+        assert_eq!(io.source().map(|source| source.to_string()), Some("record cut short".to_string()));
+        assert_eq!(
+            Error::notation("r0", 3).to_string(),
+            "Failed to parse notation 'r0' at offset 3",
+        );
+    }
+}
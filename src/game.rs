@@ -0,0 +1,1523 @@
+use std::convert::TryFrom;
+use std::fmt;
+use strum::IntoEnumIterator;
+use crate::{placeholder_deck, Board, Card, CardDisplay, Color, Error, GameResult, Hand, Move, Player, Position, Reason, Reserve, Stack, Supply};
+use crate::board::KEY_BYTES;
+use crate::encoding::{header, Decoder, HEADER_BYTES};
+use crate::notation::{self, normalized, split, tokenize, words, Diagnostic, Mode};
+use crate::player::STARTING_REEF;
+
+/// Tag of a game in the binary encoding.
+const GAME_TAG: u8 = b'G';
+
+/// Offsets of the number of players, the player on turn and the end of a
+/// game in its binary encoding, right after the header.
+const COUNT_OFFSET: usize = HEADER_BYTES;
+const CURRENT_OFFSET: usize = COUNT_OFFSET + 1;
+const END_OFFSET: usize = CURRENT_OFFSET + 1;
+
+/// One of the actions a player may take on their turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Take the top card of a display pile together with its pieces.
+    ///
+    /// When the pieces don't fit into the reserve, the listed pieces are
+    /// returned to the supply, either from the reserve or from those taken.
+    Take(usize, Vec<Color>),
+    /// Play a card from hand by its identifier, placing its two pieces.
+    Play(u8, [(Position, Stack); 2]),
+    /// Discard a card from hand by its identifier, removing it from the game.
+    ///
+    /// Allowed only when the player can neither take nor play a card, e.g.
+    /// with a full hand of cards their reserve can't pay for.
+    Discard(u8),
+}
+
+impl Action {
+    /// Notation of the action, see `TryFrom<&str>`.
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Take from a pile numbered from 1, e.g. `T 2 -rb`, play of a card with
+/// its two placements, e.g. `P c17 b2j3 g1k4`, or discard of a card, e.g.
+/// `D c17`.
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Take(pile, discard) if discard.is_empty() => write!(f, "T {}", pile + 1),
+            Action::Take(pile, discard) => write!(f, "T {} {}", pile + 1, discard_notation(discard)),
+            Action::Play(id, [(first, a), (second, b)]) => {
+                write!(f, "P c{} {}{} {}{}", id, a, first, b, second)
+            },
+            Action::Discard(id) => write!(f, "D c{}", id),
+        }
+    }
+}
+
+impl TryFrom<&str> for Action {
+    type Error = Error;
+
+    /// Parse the notation of a take, a play or a discard.
+    ///
+    /// A take names the pile, numbered from 1, followed by the pieces
+    /// discarded to make room in the reserve, if any: `T 2` or `T 2 -rb`. A
+    /// play names a card by its reference and its two placements, which have
+    /// to use the pieces of the card in either order once applied:
+    /// `P c17 b2j3 g1k4`. A discard names the card only: `D c17`.
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        Action::parse(notation, Mode::Strict).map_err(|diagnostics| diagnostics[0].to_error(notation))
+    }
+}
+
+impl Action {
+    /// Parse the notation of an action, see `TryFrom<&str>`, reporting every
+    /// problem found instead of only the first one.
+    pub fn parse(notation: &str, mode: Mode) -> Result<Action, Vec<Diagnostic>> {
+        let (tokens, diagnostics) = tokenize(notation, mode);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        let kind = tokens.first().map(|token| match mode {
+            Mode::Strict => token.text.to_string(),
+            Mode::Lenient => token.text.to_ascii_uppercase(),
+        });
+        let whole = || {
+            let message = "expected a take such as T 2 -rb, a play such as P c17 b2j3 g1k4 or a discard such as D c17";
+            vec![Diagnostic::new(0..notation.len(), message.to_string())]
+        };
+        match (kind.as_deref(), &tokens[..]) {
+            (Some("T"), [_, pile, discard @ ..]) if discard.len() <= 1 => {
+                let number = parse_number::<usize>(pile.text, 0).ok().filter(|number| *number > 0);
+                let number = number.ok_or_else(|| {
+                    let message = format!("expected a pile number starting at 1, found '{}'", pile.text);
+                    vec![Diagnostic::new(pile.span.clone(), message)]
+                });
+                let discard = match discard {
+                    [discard] => notation::discard(discard, mode),
+                    _ => Ok(Vec::new()),
+                };
+                match (number, discard) {
+                    (Ok(number), Ok(discard)) => Ok(Action::Take(number - 1, discard)),
+                    (number, discard) => Err(number.err().into_iter().chain(discard.err()).flatten().collect()),
+                }
+            },
+            (Some("P"), [_, reference, first, second]) => {
+                let (id, placements) = notation::play(reference, first, second, mode)?;
+                Ok(Action::Play(id, placements))
+            },
+            (Some("D"), [_, reference]) => {
+                let id = notation::reference(reference, mode).map_err(|diagnostic| vec![diagnostic])?;
+                Ok(Action::Discard(id))
+            },
+            (Some("T"), _) | (Some("P"), _) | (Some("D"), _) | (None, _) => Err(whole()),
+            (Some(_), tokens) => {
+                let message = format!("expected T for a take, P for a play or D for a discard, found '{}'", tokens[0].text);
+                Err(vec![Diagnostic::new(tokens[0].span.clone(), message)])
+            },
+        }
+    }
+}
+
+/// Why a game has ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EndReason {
+    /// The given display pile has run out of cards.
+    PileExhausted(usize),
+    /// The supply has run out of pieces of the given colour.
+    SupplyExhausted(Color),
+    /// The given player has no card in hand and can't take any, which ends
+    /// the game at once instead of after the round.
+    Blocked(usize),
+}
+
+/// State of a whole game of Reef.
+///
+/// Every applied action is kept in an unlimited history together with what
+/// it changed, so actions can be undone and redone. Games compare equal when
+/// their positions do, regardless of their history.
+#[derive(Debug, Clone)]
+pub struct Game {
+    players: Vec<Player>,
+    current: usize,
+    display: CardDisplay,
+    supply: Supply,
+    end: Option<EndReason>,
+    over: bool,
+    history: Vec<Turn>,
+    undone: Vec<Action>,
+}
+
+/// Applied action together with what it changed, enough to take it back.
+#[derive(Debug, Clone)]
+struct Turn {
+    action: Action,
+    /// Player who took the action.
+    current: usize,
+    change: Change,
+    /// What ended the game as known before the action.
+    end: Option<EndReason>,
+}
+
+/// What an action changed for the player who took it.
+#[derive(Debug, Clone)]
+enum Change {
+    /// Card taken into the hand, with the pieces it added to the reserve and
+    /// those discarded from the reserve to make room. The supply gave the
+    /// pieces of the card and got back all the pieces discarded.
+    Take { card: Card, added: Vec<Color>, removed: Vec<Color> },
+    /// Card played from its place in hand, with the stacks covered by its
+    /// two placements and the points it scored.
+    Play { index: usize, card: Card, covered: [Option<Stack>; 2], score: u32 },
+    /// Card discarded from its place in hand.
+    Discard { index: usize, card: Card },
+}
+
+impl Change {
+    /// Card the action took, played or discarded.
+    fn card(&self) -> &Card {
+        match self {
+            Change::Take { card, .. } | Change::Play { card, .. } | Change::Discard { card, .. } => card,
+        }
+    }
+}
+
+impl Game {
+    /// Set up a game for two to four players with the placeholder deck
+    /// shuffled by a seed.
+    ///
+    /// The same seed always produces the same game.
+    pub fn new(players: usize, seed: u64) -> Result<Game, Error> {
+        Game::with_display(players, CardDisplay::new(placeholder_deck(), seed))
+    }
+
+    /// Set up a game for two to four players with a prepared display.
+    pub fn with_display(players: usize, display: CardDisplay) -> Result<Game, Error> {
+        if !(2..=4).contains(&players) {
+            return Err(Error::OutOfBounds);
+        }
+        let players: Vec<Player> = (0..players).map(|_| Player::new()).collect();
+        let mut supply = Supply::default();
+        for _ in players.iter() {
+            for (_, color) in STARTING_REEF.iter() {
+                supply.take(*color)?;
+            }
+        }
+        Ok(Game {
+            players,
+            current: 0,
+            display,
+            supply,
+            end: None,
+            over: false,
+            history: Vec::new(),
+            undone: Vec::new(),
+        })
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    /// Index of the player whose turn it is.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn display(&self) -> &CardDisplay {
+        &self.display
+    }
+
+    pub fn supply(&self) -> &Supply {
+        &self.supply
+    }
+
+    /// Check whether the game has ended and no more actions are accepted.
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /// Reason the game is ending, known from the turn that triggered the end.
+    ///
+    /// The current round is still played out, so that every player gets the
+    /// same number of turns, before `is_over` becomes true.
+    pub fn end_reason(&self) -> Option<EndReason> {
+        self.end
+    }
+
+    /// Final scores and ranking, available once the game is over.
+    pub fn result(&self) -> Option<GameResult> {
+        if self.over {
+            Some(GameResult::new(&self.players))
+        } else {
+            None
+        }
+    }
+
+    /// Compact notation of the whole game state, see `TryFrom<&str>`.
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+
+    /// Compact binary encoding of the game state without its history.
+    ///
+    /// After a versioned header come the number of players, the player on
+    /// turn, what ended the game and the supply counts. Every player follows
+    /// with their board key, reserve counts, hand and score, and finally the
+    /// display piles with all their cards from the bottom up. Every card is
+    /// stored with its whole notation, so games played with any deck decode
+    /// to the same cards.
+    ///
+    /// Fails with `Error::OutOfBounds` for a display of more than 15 piles
+    /// or 255 cards in a pile, which don't fit the encoding.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let count = |count: usize, limit: u8| u8::try_from(count).ok().filter(|count| *count <= limit).ok_or(Error::OutOfBounds);
+        let mut bytes = header(GAME_TAG);
+        bytes.push(self.players.len() as u8);
+        bytes.push(self.current as u8);
+        bytes.push(match self.end {
+            None => 0,
+            Some(EndReason::PileExhausted(pile)) => 0x10 | pile as u8,
+            Some(EndReason::SupplyExhausted(color)) => 0x20 | color as u8,
+            Some(EndReason::Blocked(player)) => 0x30 | player as u8,
+        });
+        bytes.extend(Color::iter().map(|color| self.supply.count(color)));
+        for player in self.players.iter() {
+            bytes.extend_from_slice(&player.board.key().to_le_bytes()[..KEY_BYTES]);
+            let reserve = |first, second| player.reserve.count(first) | player.reserve.count(second) << 4;
+            bytes.push(reserve(Color::Red, Color::Green));
+            bytes.push(reserve(Color::Blue, Color::Yellow));
+            bytes.push(player.hand.len() as u8);
+            for card in player.hand.cards() {
+                encode_card(card, &mut bytes)?;
+            }
+            bytes.extend_from_slice(&player.score.to_le_bytes());
+        }
+        bytes.push(count(self.display.piles(), 0xf)?);
+        for pile in 0..self.display.piles() {
+            let cards = self.display.cards(pile);
+            bytes.push(count(cards.len(), u8::MAX)?);
+            for card in cards {
+                encode_card(card, &mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Game, Error> {
+        let mut decoder = Decoder::new(bytes, GAME_TAG)?;
+        let mut seen = Vec::new();
+        let mut card = |decoder: &mut Decoder| -> Result<Card, Error> {
+            let offset = decoder.offset();
+            let length = decoder.u16()?;
+            let card = std::str::from_utf8(decoder.take(length.into())?)
+                .ok()
+                .and_then(|notation| Card::try_from(notation).ok())
+                .filter(|card| !seen.contains(&card.id()))
+                .ok_or(Error::InvalidEncoding(offset))?;
+            seen.push(card.id());
+            Ok(card)
+        };
+
+        let count = decoder.byte()? as usize;
+        if !(2..=4).contains(&count) {
+            return Err(Error::InvalidEncoding(COUNT_OFFSET));
+        }
+        let current = decoder.byte()? as usize;
+        if current >= count {
+            return Err(Error::InvalidEncoding(CURRENT_OFFSET));
+        }
+        let end = decoder.byte()?;
+        let mut supply = Supply::new(0);
+        for color in Color::iter() {
+            for _ in 0..decoder.byte()? {
+                supply.put(color);
+            }
+        }
+
+        let mut players = Vec::with_capacity(count);
+        for _ in 0..count {
+            let offset = decoder.offset();
+            let board = Board::decode_key(decoder.take(KEY_BYTES)?).map_err(|error| error.shifted(offset))?;
+            let mut reserve = Reserve::new();
+            for colors in [[Color::Red, Color::Green], [Color::Blue, Color::Yellow]].iter() {
+                let offset = decoder.offset();
+                let counts = decoder.byte()?;
+                for (color, count) in colors.iter().zip([counts & 0xf, counts >> 4].iter()) {
+                    for _ in 0..*count {
+                        reserve.add(*color).map_err(|_| Error::InvalidEncoding(offset))?;
+                    }
+                }
+            }
+            let offset = decoder.offset();
+            let mut hand = Hand::new();
+            for _ in 0..decoder.byte()? {
+                hand.add(card(&mut decoder)?).map_err(|_| Error::InvalidEncoding(offset))?;
+            }
+            let score = decoder.u32()?;
+            players.push(Player { board, hand, reserve, score });
+        }
+
+        let mut piles = Vec::new();
+        for _ in 0..decoder.byte()? {
+            let mut cards = Vec::new();
+            for _ in 0..decoder.byte()? {
+                cards.push(card(&mut decoder)?);
+            }
+            piles.push(cards);
+        }
+        let end = match end {
+            0 => None,
+            pile if pile >> 4 == 1 && ((pile & 0xf) as usize) < piles.len() => {
+                Some(EndReason::PileExhausted((pile & 0xf) as usize))
+            },
+            color if color >> 4 == 2 && color & 0xf < 4 => {
+                Some(EndReason::SupplyExhausted(Color::iter().nth((color & 0xf) as usize).expect("colour is known")))
+            },
+            player if player >> 4 == 3 && ((player & 0xf) as usize) < count => {
+                Some(EndReason::Blocked((player & 0xf) as usize))
+            },
+            _ => return Err(Error::InvalidEncoding(END_OFFSET)),
+        };
+        decoder.finish()?;
+
+        let mut game = Game {
+            players,
+            current,
+            display: CardDisplay::from_piles(piles),
+            supply,
+            end,
+            over: false,
+            history: Vec::new(),
+            undone: Vec::new(),
+        };
+        game.over = game.finished();
+        Ok(game)
+    }
+
+    /// Every action the current player may legally take.
+    ///
+    /// Take actions come first, ordered by pile and then by the pieces
+    /// discarded. Play actions follow in hand order. Placements on different
+    /// positions are listed once, with the lower position first, while both
+    /// orders are listed when stacking two different colours on one position.
+    /// Only when there is neither a take nor a play, a discard of every card
+    /// in hand is listed. The list is empty only once the game is over.
+    pub fn legal_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        if self.over {
+            return actions;
+        }
+        let player = &self.players[self.current];
+        if !player.hand.is_full() {
+            for pile in 0..self.display.piles() {
+                if let Some(card) = self.display.top(pile).filter(|card| self.supplies(card)) {
+                    let gained = card.pieces().to_vec();
+                    let mut pool: Vec<Color> = Color::iter()
+                        .flat_map(|color| vec![color; player.reserve.count(color) as usize])
+                        .chain(gained.iter().copied())
+                        .collect();
+                    pool.sort();
+                    for discard in discards(&pool, player.reserve.overflow(&gained)) {
+                        actions.push(Action::Take(pile, discard));
+                    }
+                }
+            }
+        }
+        for card in player.hand.cards() {
+            let [first, second] = card.pieces();
+            if !player.reserve.contains(&[first, second]) {
+                continue;
+            }
+            let orders = if first == second {
+                vec![[first, second]]
+            } else {
+                vec![[first, second], [second, first]]
+            };
+            for [first, second] in orders {
+                for position in Position::iter() {
+                    let stack = match player.board.placement(position, first) {
+                        Some(stack) => stack,
+                        None => continue,
+                    };
+                    for other in Position::iter().filter(|other| *other >= position) {
+                        let top = if other == position {
+                            stack.tier.next().map(|tier| Stack { color: second, tier })
+                        } else {
+                            player.board.placement(other, second)
+                        };
+                        if let Some(top) = top {
+                            actions.push(Action::Play(card.id(), [(position, stack), (other, top)]));
+                        }
+                    }
+                }
+            }
+        }
+        if actions.is_empty() {
+            actions.extend(player.hand.cards().iter().map(|card| Action::Discard(card.id())));
+        }
+        actions
+    }
+
+    /// Whether the current player has no card in hand and can't take any.
+    fn blocked(&self) -> bool {
+        self.players[self.current].hand.is_empty()
+            && (0..self.display.piles()).all(|pile| self.display.top(pile).filter(|card| self.supplies(card)).is_none())
+    }
+
+    /// Whether the game with its end reason and player on turn is over.
+    fn finished(&self) -> bool {
+        match self.end {
+            None => false,
+            Some(EndReason::Blocked(_)) => true,
+            Some(_) => self.current == 0 || self.blocked(),
+        }
+    }
+
+    /// Whether the supply still holds both pieces that come with a card.
+    fn supplies(&self, card: &Card) -> bool {
+        let mut supply = self.supply.clone();
+        card.pieces().iter().all(|color| supply.take(*color).is_ok())
+    }
+
+    /// Actions applied so far, oldest first.
+    pub fn history(&self) -> Vec<&Action> {
+        self.history.iter().map(|turn| &turn.action).collect()
+    }
+
+    /// Actions applied so far as moves of a game record.
+    pub(crate) fn moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.history.iter().map(|turn| match &turn.action {
+            Action::Take(_, discard) => Move::Take(turn.change.card().id(), discard.clone()),
+            Action::Play(id, placements) => Move::Play(*id, *placements),
+            Action::Discard(id) => Move::Discard(*id),
+        })
+    }
+
+    /// Perform an action for the current player and pass the turn.
+    ///
+    /// An illegal action leaves the game untouched.
+    pub fn apply(&mut self, action: Action) -> Result<(), Error> {
+        self.perform(action)?;
+        self.undone.clear();
+        Ok(())
+    }
+
+    /// Take back the last action, restoring the game state before it.
+    pub fn undo(&mut self) -> Option<Action> {
+        let turn = self.history.pop()?;
+        let player = &mut self.players[turn.current];
+        match (&turn.action, turn.change) {
+            (Action::Take(pile, discard), Change::Take { card, added, removed }) => {
+                for color in discard.iter() {
+                    self.supply.take(*color).expect("supply holds the discarded pieces");
+                }
+                for color in card.pieces().iter() {
+                    self.supply.put(*color);
+                }
+                player.reserve.remove(&added).expect("reserve holds the pieces taken");
+                for color in removed {
+                    player.reserve.add(color).expect("reserve held the discarded pieces");
+                }
+                player.hand.remove(card.id()).expect("hand holds the card taken");
+                self.display.put_back(*pile, card);
+            },
+            (Action::Play(_, [(first, _), (second, _)]), Change::Play { index, card, covered, score }) => {
+                player.board.take_back(*second, covered[1]);
+                player.board.take_back(*first, covered[0]);
+                for color in card.pieces().iter() {
+                    player.reserve.add(*color).expect("reserve held the pieces played");
+                }
+                player.hand.insert(index, card);
+                player.score -= score;
+            },
+            (_, Change::Discard { index, card }) => player.hand.insert(index, card),
+            (_, change) => unreachable!("{:?} does not match {:?}", change, turn.action),
+        }
+        self.current = turn.current;
+        self.end = turn.end;
+        self.over = false;
+        self.undone.push(turn.action.clone());
+        Some(turn.action)
+    }
+
+    /// Apply the last undone action again.
+    pub fn redo(&mut self) -> Option<Action> {
+        let action = self.undone.pop()?;
+        self.perform(action.clone()).expect("undone action is legal");
+        Some(action)
+    }
+
+    fn perform(&mut self, action: Action) -> Result<(), Error> {
+        if self.over {
+            return Err(Error::rule(Reason::GameOver));
+        }
+        let change = match &action {
+            Action::Take(pile, discard) => self.take(*pile, discard)?,
+            Action::Play(card, placements) => self.play(*card, *placements)?,
+            Action::Discard(card) => self.discard(*card)?,
+        };
+        let turn = Turn {
+            action,
+            current: self.current,
+            change,
+            end: self.end,
+        };
+        if self.end.is_none() {
+            self.end = self.display
+                .empty_pile()
+                .map(EndReason::PileExhausted)
+                .or_else(|| self.supply.exhausted().map(EndReason::SupplyExhausted));
+        }
+        self.current = (self.current + 1) % self.players.len();
+        if self.end.is_none() && self.blocked() {
+            self.end = Some(EndReason::Blocked(self.current));
+        }
+        self.over = self.finished();
+        self.history.push(turn);
+        Ok(())
+    }
+
+    /// Take the top card of a pile with its pieces.
+    fn take(&mut self, pile: usize, discard: &[Color]) -> Result<Change, Error> {
+        let player = &mut self.players[self.current];
+        if player.hand.is_full() {
+            return Err(Error::rule(Reason::HandFull));
+        }
+        if pile >= self.display.piles() {
+            return Err(Error::rule(Reason::NoSuchPile));
+        }
+        let card = self.display.top(pile).ok_or(Error::rule(Reason::PileEmpty))?;
+        let mut supply = self.supply.clone();
+        let mut pieces = card.pieces().to_vec();
+        for color in pieces.iter() {
+            supply.take(*color)?;
+        }
+        let overflow = player.reserve.overflow(&pieces);
+        if discard.len() < overflow {
+            return Err(Error::rule(Reason::ReserveFull));
+        } else if discard.len() > overflow {
+            return Err(Error::rule(Reason::WrongDiscard));
+        }
+        let mut reserve = player.reserve.clone();
+        let mut removed = Vec::new();
+        for color in discard.iter() {
+            if let Some(index) = pieces.iter().position(|piece| piece == color) {
+                pieces.remove(index);
+            } else {
+                reserve.remove(&[*color]).map_err(|error| error.because(Reason::WrongDiscard))?;
+                removed.push(*color);
+            }
+            supply.put(*color);
+        }
+        for color in pieces.iter() {
+            reserve.add(*color)?;
+        }
+        let card = self.display.take(pile)?;
+        player.hand.add(card.clone())?;
+        player.reserve = reserve;
+        self.supply = supply;
+        Ok(Change::Take { card, added: pieces, removed })
+    }
+
+    fn play(&mut self, id: u8, placements: [(Position, Stack); 2]) -> Result<Change, Error> {
+        let player = &mut self.players[self.current];
+        let index = player.hand.index(id).ok_or(Error::rule(Reason::CardNotInHand))?;
+        let (card, covered, score) = player.play(id, placements)?;
+        Ok(Change::Play { index, card, covered, score })
+    }
+
+    fn discard(&mut self, id: u8) -> Result<Change, Error> {
+        let index = self.players[self.current].hand.index(id).ok_or(Error::rule(Reason::CardNotInHand))?;
+        if self.legal_actions().iter().any(|action| !matches!(action, Action::Discard(_))) {
+            return Err(Error::rule(Reason::MovesLeft));
+        }
+        let card = self.players[self.current].hand.remove(id)?;
+        Ok(Change::Discard { index, card })
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let players: Vec<String> = self.players
+            .iter()
+            .map(|player| {
+                let board = match player.board.to_notation() {
+                    board if board.is_empty() => "-".to_string(),
+                    board => board.replace(' ', ","),
+                };
+                format!("{}:{}:{}:{}", board, player.reserve, player.hand, player.score)
+            })
+            .collect();
+        let piles: Vec<String> = (0..self.display.piles())
+            .map(|pile| match self.display.cards(pile) {
+                [] => "-".to_string(),
+                cards => cards.iter().map(|card| card.reference()).collect::<Vec<String>>().join(","),
+            })
+            .collect();
+        let end = match self.end {
+            None => "-".to_string(),
+            Some(EndReason::PileExhausted(pile)) => format!("p{}", pile + 1),
+            Some(EndReason::SupplyExhausted(color)) => color.to_notation(),
+            Some(EndReason::Blocked(player)) => format!("x{}", player + 1),
+        };
+        write!(f, "{} {} {} {} {}", players.join("/"), self.current + 1, piles.join("/"), self.supply, end)
+    }
+}
+
+impl TryFrom<&str> for Game {
+    type Error = Error;
+
+    /// Parse the notation of a whole game state.
+    ///
+    /// The notation consists of five fields separated by spaces:
+    ///
+    /// * players separated by `/`, each as `board:reserve:hand:score` with
+    ///   board placements separated by commas, e.g. `r1j3,g2k3:rb:c3,c17:12`
+    /// * number of the player on turn, starting at 1
+    /// * display piles separated by `/`, each as the references of its cards
+    ///   from the bottom up separated by commas, e.g. `c5,c9,c45`, or `-`
+    ///   when empty
+    /// * supply counts, e.g. `r26g26b24y25`
+    /// * `-` while the game goes on, or what ended it: `p2` for an exhausted
+    ///   second pile, the colour letter of an exhausted supply, or `x2` for a
+    ///   blocked second player
+    ///
+    /// Empty boards, reserves and hands are written as `-`. Cards are looked
+    /// up in the placeholder deck, see `Game::parse_with_deck` for games
+    /// played with other cards, and every card may appear only once.
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        Game::parse(notation, Mode::Strict).map_err(|diagnostics| diagnostics[0].to_error(notation))
+    }
+}
+
+impl Game {
+    /// Parse the notation of a game state, see `TryFrom<&str>`, reporting
+    /// every problem found instead of only the first one.
+    ///
+    /// In lenient mode, the fields may be separated by any whitespace and
+    /// letters may be written in either case, with `v` for blue.
+    pub fn parse(notation: &str, mode: Mode) -> Result<Game, Vec<Diagnostic>> {
+        Game::parse_with_deck(notation, &placeholder_deck(), mode)
+    }
+
+    /// Parse the notation of a game state played with the given deck, whose
+    /// cards the references in hands and piles are looked up in.
+    pub fn parse_with_deck(notation: &str, deck: &[Card], mode: Mode) -> Result<Game, Vec<Diagnostic>> {
+        let (tokens, diagnostics) = words(notation, mode);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        if tokens.len() != 5 {
+            let message = "expected five fields: players, player on turn, piles, supply and end";
+            return Err(vec![Diagnostic::new(0..notation.len(), message.to_string())]);
+        }
+        let fields: Vec<(usize, String)> = tokens.iter().map(|token| (token.span.start, normalized(token.text, mode))).collect();
+        let mut diagnostics = Vec::new();
+
+        let (offset, field) = (fields[0].0, fields[0].1.as_str());
+        let seats = split(field, '/', offset);
+        let mut players = Vec::new();
+        let mut seen = Vec::new();
+        for (offset, player) in seats.iter() {
+            match parse_player(player, *offset, deck, &mut seen) {
+                Ok(player) => players.push(player),
+                Err(found) => diagnostics.extend(found),
+            }
+        }
+        if !(2..=4).contains(&seats.len()) {
+            let message = format!("a game has 2 to 4 players, found {}", seats.len());
+            diagnostics.push(Diagnostic::new(offset..offset + field.len(), message));
+        }
+
+        let (offset, turn) = (fields[1].0, fields[1].1.as_str());
+        let current = parse_number::<usize>(turn, offset)
+            .ok()
+            .filter(|current| (1..=seats.len()).contains(current))
+            .ok_or_else(|| {
+                let message = format!("expected the number of the player on turn, from 1 to {}, found '{}'", seats.len(), turn);
+                diagnostics.push(Diagnostic::new(offset..offset + turn.len(), message));
+            });
+
+        let (offset, field) = (fields[2].0, fields[2].1.as_str());
+        let mut piles = Vec::new();
+        for (offset, pile) in split(field, '/', offset) {
+            match parse_pile(pile, offset, deck, &mut seen) {
+                Ok(cards) => piles.push(cards),
+                Err(found) => diagnostics.push(found),
+            }
+        }
+
+        let (offset, supply) = (fields[3].0, fields[3].1.as_str());
+        let supply = Supply::try_from(supply).map_err(|error| {
+            let message = format!("expected supply counts such as r26g26b24y25, found '{}'", supply);
+            diagnostics.push(Diagnostic::from_error(&error.shifted(offset), message));
+        });
+
+        let (offset, end) = (fields[4].0, fields[4].1.as_str());
+        let end = parse_end(end, offset, split(field, '/', 0).len(), seats.len()).map_err(|error| {
+            let message = format!("expected -, an exhausted pile such as p2, a colour or a blocked player such as x2, found '{}'", end);
+            diagnostics.push(Diagnostic::from_error(&error, message));
+        });
+
+        match (current, supply, end) {
+            (Ok(current), Ok(supply), Ok(end)) if diagnostics.is_empty() => {
+                let mut game = Game {
+                    players,
+                    current: current - 1,
+                    display: CardDisplay::from_piles(piles),
+                    supply,
+                    end,
+                    over: false,
+                    history: Vec::new(),
+                    undone: Vec::new(),
+                };
+                game.over = game.finished();
+                Ok(game)
+            },
+            _ => {
+                diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+                Err(diagnostics)
+            },
+        }
+    }
+}
+
+/// Parse a player of the game notation, e.g. `r1j3,g2k3:rb:c3,c17:12`,
+/// adding the cards in hand to those seen.
+fn parse_player(notation: &str, offset: usize, deck: &[Card], seen: &mut Vec<u8>) -> Result<Player, Vec<Diagnostic>> {
+    let parts = split(notation, ':', offset);
+    if parts.len() != 4 {
+        let message = format!("expected a player such as r1j3,g2k3:rb:c3,c17:12, found '{}'", notation);
+        return Err(vec![Diagnostic::new(offset..offset + notation.len(), message)]);
+    }
+    let board = match parts[0] {
+        (_, "-") => Ok(Board::new()),
+        (offset, board) => Board::parse(&board.replace(',', " "), Mode::Strict)
+            .map_err(|diagnostics| diagnostics.into_iter().map(|diagnostic| diagnostic.shifted(offset)).collect()),
+    };
+    let (offset, reserve) = parts[1];
+    let reserve = Reserve::try_from(reserve).map_err(|error| {
+        let message = format!("expected reserve pieces such as rb, or -, found '{}'", reserve);
+        vec![Diagnostic::from_error(&error.shifted(offset), message)]
+    });
+    let (offset, hand) = parts[2];
+    let hand = Hand::parse(hand, deck)
+        .map_err(|error| {
+            let message = format!("expected cards of the deck such as c3,c17, or -, found '{}'", hand);
+            vec![Diagnostic::from_error(&error.shifted(offset), message)]
+        })
+        .and_then(|parsed| {
+            for ((offset, reference), card) in split(hand, ',', offset).into_iter().zip(parsed.cards()) {
+                claim(card, reference, offset, seen).map_err(|diagnostic| vec![diagnostic])?;
+            }
+            Ok(parsed)
+        });
+    let (offset, score) = parts[3];
+    let score = parse_number(score, offset).map_err(|error| {
+        vec![Diagnostic::from_error(&error, format!("expected a score, found '{}'", score))]
+    });
+    match (board, reserve, hand, score) {
+        (Ok(board), Ok(reserve), Ok(hand), Ok(score)) => Ok(Player { board, hand, reserve, score }),
+        (board, reserve, hand, score) => Err(board.err().into_iter()
+            .chain(reserve.err())
+            .chain(hand.err())
+            .chain(score.err())
+            .flatten()
+            .collect()),
+    }
+}
+
+/// Parse a display pile of the game notation, its cards from the bottom up
+/// such as `c5,c9,c45`, or `-` when empty, adding them to those seen.
+fn parse_pile(pile: &str, offset: usize, deck: &[Card], seen: &mut Vec<u8>) -> Result<Vec<Card>, Diagnostic> {
+    let mut cards = Vec::new();
+    if pile == "-" {
+        return Ok(cards);
+    }
+    for (offset, reference) in split(pile, ',', offset) {
+        let card = Card::parse_reference(reference)
+            .map_err(|error| error.shifted(offset))
+            .and_then(|id| deck.iter().find(|card| card.id() == id).ok_or_else(|| Error::notation(reference, offset)))
+            .map_err(|error| {
+                let message = format!("expected a pile of cards of the deck such as c5,c9,c45, or -, found '{}'", pile);
+                Diagnostic::from_error(&error, message)
+            })?;
+        claim(card, reference, offset, seen)?;
+        cards.push(card.clone());
+    }
+    Ok(cards)
+}
+
+/// Note a card as seen in the game, which holds every card only once.
+fn claim(card: &Card, reference: &str, offset: usize, seen: &mut Vec<u8>) -> Result<(), Diagnostic> {
+    if seen.contains(&card.id()) {
+        let message = format!("card {} appears more than once in the game", reference);
+        return Err(Diagnostic::new(offset..offset + reference.len(), message));
+    }
+    seen.push(card.id());
+    Ok(())
+}
+
+/// Parse what ended a game, given the number of piles and players.
+fn parse_end(end: &str, offset: usize, piles: usize, players: usize) -> Result<Option<EndReason>, Error> {
+    match end {
+        "-" => Ok(None),
+        pile if pile.starts_with('p') => {
+            let pile: usize = parse_number(&pile[1..], offset + 1)?;
+            if !(1..=piles).contains(&pile) {
+                return Err(Error::notation(end, offset));
+            }
+            Ok(Some(EndReason::PileExhausted(pile - 1)))
+        },
+        player if player.starts_with('x') && player.len() > 1 => {
+            let player: usize = parse_number(&player[1..], offset + 1)?;
+            if !(1..=players).contains(&player) {
+                return Err(Error::notation(end, offset));
+            }
+            Ok(Some(EndReason::Blocked(player - 1)))
+        },
+        color => Ok(Some(EndReason::SupplyExhausted(Color::try_from(color).map_err(|error| error.shifted(offset))?))),
+    }
+}
+
+/// Append a card to a binary encoding as the length of its notation followed
+/// by the notation itself.
+fn encode_card(card: &Card, bytes: &mut Vec<u8>) -> Result<(), Error> {
+    let notation = card.to_notation();
+    let length = u16::try_from(notation.len()).map_err(|_| Error::OutOfBounds)?;
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(notation.as_bytes());
+    Ok(())
+}
+
+/// Pieces discarded on a take, e.g. `-rb`.
+pub(crate) fn discard_notation(discard: &[Color]) -> String {
+    let colors: String = discard.iter().map(|color| color.to_notation()).collect();
+    format!("-{}", colors)
+}
+
+/// Parse a decimal number made of ASCII digits only.
+fn parse_number<T: std::str::FromStr>(notation: &str, offset: usize) -> Result<T, Error> {
+    if notation.is_empty() || !notation.bytes().all(|digit| digit.is_ascii_digit()) {
+        return Err(Error::notation(notation, offset));
+    }
+    notation.parse().map_err(|_| Error::notation(notation, offset))
+}
+
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.players == other.players
+            && self.current == other.current
+            && self.display == other.display
+            && self.supply == other.supply
+            && self.end == other.end
+            && self.over == other.over
+    }
+}
+
+impl Eq for Game {}
+
+/// Distinct choices of `count` pieces out of a sorted pool.
+fn discards(pool: &[Color], count: usize) -> Vec<Vec<Color>> {
+    if count == 0 {
+        return vec![Vec::new()];
+    }
+    let mut choices = Vec::new();
+    for (index, color) in pool.iter().enumerate() {
+        if index > 0 && pool[index - 1] == *color {
+            continue;
+        }
+        for mut rest in discards(&pool[index + 1..], count - 1) {
+            rest.insert(0, *color);
+            choices.push(rest);
+        }
+    }
+    choices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use crate::{parse_deck, placeholder_deck, Board, Tier, PIECES_PER_COLOR, PILES};
+
+    /// Game with the placeholder deck dealt into the piles in order.
+    fn unshuffled(players: usize) -> Result<Game, Error> {
+        let mut piles = vec![Vec::new(); PILES];
+        for (index, card) in placeholder_deck().into_iter().enumerate() {
+            piles[index % PILES].push(card);
+        }
+        Game::with_display(players, CardDisplay::from_piles(piles))
+    }
+
+    #[test]
+    fn game_setup() -> Result<(), Error> {
+        assert!(Game::new(1, 0).is_err());
+        assert!(Game::new(5, 0).is_err());
+        let game = Game::new(3, 0)?;
+        assert_eq!(game.players().len(), 3);
+        assert_eq!(game.current(), 0);
+        assert_eq!(game.supply().count(Color::Red), 25);
+        assert_eq!(game.display().piles(), PILES);
+        for player in game.players() {
+            assert_eq!(player.board().get(&Position::j3), Some(&Stack::try_from("r1")?));
+            assert_eq!(player.hand().len(), 0);
+            assert_eq!(player.score(), 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn game_on_thread() -> Result<(), Error> {
+        let game = Game::new(2, 5)?;
+        let actions = game.legal_actions();
+        let worker = std::thread::spawn(move || game.legal_actions());
+        assert_eq!(worker.join().unwrap(), actions);
+        Ok(())
+    }
+
+    #[test]
+    fn game_take() -> Result<(), Error> {
+        let mut game = unshuffled(2)?;
+        let card = game.display().top(0).cloned().unwrap();
+        game.apply(Action::Take(0, vec![]))?;
+        assert_eq!(game.current(), 1);
+        let player = &game.players()[0];
+        assert_eq!(player.hand().cards(), std::slice::from_ref(&card));
+        assert_eq!(player.reserve().len(), 2);
+        assert_eq!(game.supply().len(), 4 * 26 - 2);
+        assert_ne!(game.display().top(0), Some(&card));
+        assert!(game.apply(Action::Take(PILES, vec![])).is_err());
+        assert_eq!(game.current(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn game_play() -> Result<(), Error> {
+        let mut game = unshuffled(2)?;
+        // top of the first pile surrounds blue with yellow and gives two yellows
+        assert_eq!(game.display().top(0).unwrap().pieces(), [Color::Yellow, Color::Yellow]);
+        let id = game.display().top(0).unwrap().id();
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        let illegal = [Board::interpret("y1i1")?, Board::interpret("r1i2")?];
+        assert_eq!(game.apply(Action::Play(id, illegal)).unwrap_err().reason(), Some(Reason::WrongPieces));
+        let illegal = [Board::interpret("y1i1")?, Board::interpret("y2i4")?];
+        let error = game.apply(Action::Play(id, illegal)).unwrap_err();
+        assert_eq!(error.reason(), Some(Reason::IllegalPlacement));
+        assert!(matches!(error, Error::IllegalMove { source: Some(source), .. } if matches!(*source, Error::IllegalPlacement {
+            position: Position::i4,
+            current: None,
+            attempted: Stack { color: Color::Yellow, tier: Tier::Second },
+        })));
+        let illegal = [Board::interpret("y1i1")?, Board::interpret("y1i2")?];
+        assert_eq!(game.apply(Action::Play(id + 1, illegal)).unwrap_err().reason(), Some(Reason::CardNotInHand));
+        assert_eq!(game.current(), 0);
+        let placements = [Board::interpret("y1i1")?, Board::interpret("y2i1")?];
+        game.apply(Action::Play(id, placements))?;
+        let player = &game.players()[0];
+        assert_eq!(player.hand().len(), 0);
+        assert!(player.reserve().is_empty());
+        assert_eq!(player.board().get(&Position::i1), Some(&Stack::try_from("y2")?));
+        // the blue piece on j2 is surrounded by the yellow stacks on i1 and k2
+        assert_eq!(player.score(), 2);
+        assert_eq!(game.current(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn game_take_limits() -> Result<(), Error> {
+        use Color::*;
+        let mut game = unshuffled(2)?;
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        assert!(game.players()[0].reserve().is_full());
+        assert_eq!(game.apply(Action::Take(0, vec![])).unwrap_err().reason(), Some(Reason::ReserveFull));
+        assert!(game.apply(Action::Take(0, vec![Yellow, Green, Green])).is_err());
+        assert!(game.apply(Action::Take(0, vec![Red, Green])).is_err());
+        let supply = game.supply().count(Yellow);
+        game.apply(Action::Take(0, vec![Yellow, Green]))?;
+        let reserve = game.players()[0].reserve();
+        assert_eq!((reserve.count(Yellow), reserve.count(Blue), reserve.count(Green)), (1, 2, 1));
+        assert_eq!(game.supply().count(Yellow), supply + 1);
+        game.apply(Action::Take(1, vec![Blue, Blue]))?;
+        game.apply(Action::Take(0, vec![Yellow, Red]))?;
+        game.apply(Action::Take(1, vec![Blue, Green]))?;
+        assert!(game.players()[0].hand().is_full());
+        assert_eq!(game.apply(Action::Take(0, vec![])).unwrap_err().reason(), Some(Reason::HandFull));
+        Ok(())
+    }
+
+    /// Number of pieces in the supply, the reserves and on the boards.
+    fn pieces(game: &Game) -> usize {
+        game.supply().len() + game.players()
+            .iter()
+            .map(|player| player.reserve().len() + player.board().pieces())
+            .sum::<usize>()
+    }
+
+    #[test]
+    fn game_end_pile_exhausted() -> Result<(), Error> {
+        let cards = placeholder_deck();
+        let display = CardDisplay::from_piles(vec![
+            vec![cards[0].clone()],
+            vec![cards[1].clone(), cards[2].clone()],
+            vec![cards[3].clone(), cards[4].clone()],
+            vec![cards[5].clone(), cards[6].clone()],
+        ]);
+        let mut game = Game::with_display(3, display)?;
+        assert_eq!(pieces(&game), 4 * PIECES_PER_COLOR as usize);
+        game.apply(Action::Take(1, vec![]))?;
+        assert_eq!(game.end_reason(), None);
+        assert_eq!(game.result(), None);
+        game.apply(Action::Take(0, vec![]))?;
+        assert_eq!(game.end_reason(), Some(EndReason::PileExhausted(0)));
+        assert!(!game.is_over());
+        game.apply(Action::Take(2, vec![]))?;
+        assert!(game.is_over());
+        assert_eq!(game.current(), 0);
+        assert!(game.apply(Action::Take(3, vec![])).is_err());
+        assert_eq!(pieces(&game), 4 * PIECES_PER_COLOR as usize);
+        let result = game.result().unwrap();
+        assert_eq!(result.winners(), vec![0, 1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn game_end_supply_exhausted() -> Result<(), Error> {
+        let mut game = unshuffled(2)?;
+        while game.supply().count(Color::Red) > 2 {
+            game.supply.take(Color::Red)?;
+        }
+        game.apply(Action::Take(0, vec![]))?;
+        assert_eq!(game.end_reason(), None);
+        // top of the second pile gives two reds
+        game.apply(Action::Take(1, vec![]))?;
+        assert_eq!(game.supply().count(Color::Red), 0);
+        assert_eq!(game.end_reason(), Some(EndReason::SupplyExhausted(Color::Red)));
+        assert!(game.is_over());
+        Ok(())
+    }
+
+    #[test]
+    fn game_take_short_supply() -> Result<(), Error> {
+        let mut game = unshuffled(2)?;
+        while game.supply().count(Color::Red) > 1 {
+            game.supply.take(Color::Red)?;
+        }
+        // top of the second pile gives two reds
+        let before = game.clone();
+        assert!(matches!(game.apply(Action::Take(1, vec![])).unwrap_err(), Error::IllegalMove { reason: Reason::SupplyEmpty, source: None }));
+        assert_eq!(game, before);
+        assert_eq!(game.supply().count(Color::Red), 1);
+        assert!(!game.legal_actions().contains(&Action::Take(1, vec![])));
+        assert!(game.legal_actions().contains(&Action::Take(0, vec![])));
+        Ok(())
+    }
+
+    #[test]
+    fn game_discard() -> Result<(), Error> {
+        // a full hand of double cards, none of them paid for by the reserve
+        let notation = "b1j2,r1j3,y1k2,g1k3:rgby:c3,c4,c10,c16:0/b1j2,r1j3,y1k2,g1k3:-:-:0 1 c7,c1/c8,c2/c9,c5/c11,c6 r20g20b20y20 -";
+        let mut game = Game::try_from(notation)?;
+        let discards: Vec<Action> = [3, 4, 10, 16].iter().map(|id| Action::Discard(*id)).collect();
+        assert_eq!(game.legal_actions(), discards);
+        assert!(matches!(game.apply(Action::Discard(1)).unwrap_err(), Error::IllegalMove { reason: Reason::CardNotInHand, source: None }));
+        game.apply(Action::Discard(4))?;
+        assert_eq!(game.players()[0].hand().to_string(), "c3,c10,c16");
+        assert_eq!(game.players()[0].reserve().len(), 4);
+        // the second player may take a card instead
+        assert!(matches!(game.apply(Action::Discard(3)).unwrap_err(), Error::IllegalMove { reason: Reason::CardNotInHand, source: None }));
+        let mut game = Game::try_from(notation.replace("rgby:c3", "rrby:c3").as_str())?;
+        assert!(!game.legal_actions().contains(&Action::Discard(3)));
+        assert!(matches!(game.apply(Action::Discard(3)).unwrap_err(), Error::IllegalMove { reason: Reason::MovesLeft, source: None }));
+        Ok(())
+    }
+
+    #[test]
+    fn game_end_blocked() -> Result<(), Error> {
+        // every top card needs two pieces of a colour the supply has one of
+        let notation = "b1j2,r1j3,y1k2,g1k3:rg:c1:0/b1j2,r1j3,y1k2,g1k3:-:-:0 1 c3/c4/c10/c16 r1g1b1y1 -";
+        let mut game = Game::try_from(notation)?;
+        assert!(game.legal_actions().iter().all(|action| matches!(action, Action::Play(1, _))));
+        game.apply(game.legal_actions()[0].clone())?;
+        assert_eq!(game.current(), 1);
+        assert_eq!(game.end_reason(), Some(EndReason::Blocked(1)));
+        assert!(game.is_over());
+        assert_eq!(game.legal_actions(), vec![]);
+        assert!(game.to_notation().ends_with(" x2"));
+        assert_eq!(Game::try_from(game.to_notation().as_str())?, game);
+        assert_eq!(Game::from_bytes(&game.to_bytes()?)?, game);
+        assert_eq!(game.undo().map(|action| game.legal_actions().contains(&action)), Some(true));
+        assert!(!game.is_over());
+        Ok(())
+    }
+
+    #[test]
+    fn game_always_has_actions() -> Result<(), Error> {
+        for seed in 0..40 {
+            let mut game = Game::new(2 + seed as usize % 3, seed)?;
+            let mut state = seed;
+            while !game.is_over() {
+                let actions = game.legal_actions();
+                assert!(!actions.is_empty(), "no action in {}", game);
+                // mostly takes, so that hands fill up with cards the reserves can't pay for
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let takes = actions.iter().filter(|action| matches!(action, Action::Take(..))).count();
+                let random = (state >> 33) as usize;
+                let index = if takes > 0 && random % 4 < 3 { random % takes } else { random % actions.len() };
+                game.apply(actions[index].clone())?;
+            }
+            assert!(game.legal_actions().is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn game_legal_actions() -> Result<(), Error> {
+        let mut game = unshuffled(2)?;
+        let takes: Vec<Action> = (0..PILES).map(|pile| Action::Take(pile, vec![])).collect();
+        assert_eq!(game.legal_actions(), takes);
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        // two yellows on a board with the four centre spaces taken
+        let actions = game.legal_actions();
+        assert_eq!(actions.len(), PILES + 120 + 16);
+        assert_eq!(actions[..PILES], takes[..]);
+        let id = game.players()[0].hand().cards()[0].id();
+        assert_eq!(actions[PILES], Action::Play(id, [Board::interpret("y1i1")?, Board::interpret("y2i1")?]));
+        for (index, action) in actions.iter().enumerate() {
+            assert!(!actions[..index].contains(action));
+            game.clone().apply(action.clone())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn game_legal_discards() -> Result<(), Error> {
+        use Color::*;
+        let mut game = unshuffled(2)?;
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        // reserve holds two yellows and two blues, the first pile gives two greens
+        let takes: Vec<Action> = game.legal_actions()
+            .into_iter()
+            .filter(|action| matches!(action, Action::Take(..)))
+            .collect();
+        assert_eq!(takes[..6], [
+            Action::Take(0, vec![Green, Green]),
+            Action::Take(0, vec![Green, Blue]),
+            Action::Take(0, vec![Green, Yellow]),
+            Action::Take(0, vec![Blue, Blue]),
+            Action::Take(0, vec![Blue, Yellow]),
+            Action::Take(0, vec![Yellow, Yellow]),
+        ]);
+        for action in takes.iter() {
+            game.clone().apply(action.clone())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn game_undo() -> Result<(), Error> {
+        let start = unshuffled(2)?;
+        let mut game = start.clone();
+        assert_eq!(game.undo(), None);
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        let taken = game.clone();
+        let id = game.players()[0].hand().cards()[0].id();
+        let play = Action::Play(id, [Board::interpret("y1i1")?, Board::interpret("y2i1")?]);
+        game.apply(play.clone())?;
+        let played = game.clone();
+        assert_eq!(game.history().len(), 3);
+        assert_eq!(game.undo(), Some(play.clone()));
+        assert_eq!(game, taken);
+        assert_eq!(game.players()[0].score(), 0);
+        assert_eq!(game.players()[0].board().get(&Position::i1), None);
+        game.undo();
+        game.undo();
+        assert_eq!(game.undo(), None);
+        assert_eq!(game, start);
+        assert_eq!(game.redo(), Some(Action::Take(0, vec![])));
+        game.redo();
+        assert_eq!(game.redo(), Some(play));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game, played);
+        assert_eq!(game.history().len(), 3);
+        game.undo();
+        game.apply(Action::Take(2, vec![]))?;
+        assert_eq!(game.redo(), None);
+        let mut game = Game::new(3, 5)?;
+        let mut states = Vec::new();
+        for turn in 0.. {
+            if game.is_over() {
+                break;
+            }
+            states.push(game.clone());
+            let actions = game.legal_actions();
+            game.apply(actions[turn * 5 % actions.len()].clone())?;
+        }
+        let over = game.clone();
+        while let Some(state) = states.pop() {
+            game.undo();
+            assert_eq!(game, state);
+            assert_eq!(game.to_bytes()?, state.to_bytes()?);
+        }
+        while game.redo().is_some() {}
+        assert_eq!(game, over);
+        let mut game = unshuffled(2)?;
+        for pile in 0..4 {
+            game.apply(Action::Take(pile, vec![]))?;
+        }
+        let before = game.clone();
+        let take = game.legal_actions().into_iter().find(|action| matches!(action, Action::Take(_, discard) if discard.len() == 2));
+        game.apply(take.expect("full reserve has to discard"))?;
+        assert_ne!(game, before);
+        game.undo();
+        assert_eq!(game, before);
+        Ok(())
+    }
+
+    #[test]
+    fn game_notation() -> Result<(), Error> {
+        // the piles of the unshuffled game, from the bottom up
+        let piles = |tops: [usize; 4]| -> String {
+            let piles: Vec<String> = (0..4)
+                .map(|pile| (pile + 1..=tops[pile]).step_by(4).map(|id| format!("c{}", id)).collect::<Vec<String>>().join(","))
+                .collect();
+            piles.join("/")
+        };
+        let start = format!("b1j2,r1j3,y1k2,g1k3:-:-:0/b1j2,r1j3,y1k2,g1k3:-:-:0 1 {} r26g26b26y26 -", piles([45, 46, 47, 48]));
+        assert!(start.ends_with("c41,c45/c2,c6,c10,c14,c18,c22,c26,c30,c34,c38,c42,c46/c3,c7,c11,c15,c19,c23,c27,c31,c35,c39,c43,c47/c4,c8,c12,c16,c20,c24,c28,c32,c36,c40,c44,c48 r26g26b26y26 -"));
+        let mut game = unshuffled(2)?;
+        assert_eq!(game.to_notation(), start);
+        let parsed = Game::try_from(start.as_str())?;
+        assert_eq!(parsed.to_notation(), start);
+        assert_eq!(parsed, game);
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        let id = game.players()[0].hand().cards()[0].id();
+        game.apply(Action::Play(id, [Board::interpret("y1i1")?, Board::interpret("y2i1")?]))?;
+        let notation = game.to_notation();
+        let expected = format!("y2i1,b1j2,r1j3,y1k2,g1k3:-:-:2/b1j2,r1j3,y1k2,g1k3:rr:c46:0 2 {} r24g26b26y24 -", piles([41, 42, 47, 48]));
+        assert_eq!(notation, expected);
+        let parsed = Game::try_from(notation.as_str())?;
+        assert_eq!(parsed.to_notation(), notation);
+        assert_eq!(parsed, game);
+        assert_eq!(parsed.current(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn game_notation_deck() -> Result<(), Error> {
+        let deck = parse_deck("c60 Line(r) 2 rg\nc61 Surround(y,b) 5 ry\nc62 Corner(g) 3 gg\n")?;
+        let notation = "-:rg:c60:0/-:-:-:0 2 c61/c62 r25g25b26y26 -";
+        let game = Game::parse_with_deck(notation, &deck, Mode::Strict).unwrap();
+        assert_eq!(game.to_notation(), notation);
+        assert_eq!(game.players()[0].hand().cards(), &deck[..1]);
+        assert_eq!(game.display().top(1), Some(&deck[2]));
+        assert!(Game::try_from(notation).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn game_notation_end() -> Result<(), Error> {
+        let notation = "-:-:-:3/-:rrgg:c1,c2:5 1 -/c4 r0g0b2y1 p1";
+        let game = Game::try_from(notation)?;
+        assert_eq!(game.to_notation(), notation);
+        assert!(game.is_over());
+        assert_eq!(game.end_reason(), Some(EndReason::PileExhausted(0)));
+        assert_eq!(game.result().unwrap().winners(), vec![1]);
+        let game = Game::try_from("-:-:-:3/-:-:c5:5 2 c3 r0g0b2y1 g")?;
+        assert!(!game.is_over());
+        assert_eq!(game.end_reason(), Some(EndReason::SupplyExhausted(Color::Green)));
+        Ok(())
+    }
+
+    #[test]
+    fn game_notation_errors() {
+        let errors = [
+            ("-:-:-:0/-:-:-:0 1 c1 r0g0b0y0", "-:-:-:0/-:-:-:0 1 c1 r0g0b0y0", 0),
+            ("-:-:-:0 1 c1 r0g0b0y0 -", "-:-:-:0", 0),
+            ("-:-:-:0/-:-:-:x 1 c1 r0g0b0y0 -", "x", 14),
+            ("-:-:-:0/r1i1,r1i1:-:-:0 1 c1 r0g0b0y0 -", "r1i1", 13),
+            ("-:-:-:0/-:-:c1,c99:0 1 c2 r0g0b0y0 -", "c99", 15),
+            ("-:-:-:0/-:-:-:0 3 c1 r0g0b0y0 -", "3", 16),
+            ("-:-:-:0/-:-:-:0 1 c1,c99 r0g0b0y0 -", "c99", 21),
+            ("-:-:-:0/-:-:-:0 1 c1,x r0g0b0y0 -", "x", 21),
+            ("-:-:-:0/-:-:-:0 1 c1/ r0g0b0y0 -", "", 21),
+            ("-:-:-:0/-:-:c1:0 1 c2,c1 r0g0b0y0 -", "c1", 22),
+            ("-:-:c1:0/-:-:c1:0 1 c2 r0g0b0y0 -", "c1", 13),
+            ("-:-:-:0/-:-:-:0 1 c1 r0g0b0 -", "", 27),
+            ("-:-:-:0/-:-:-:0 1 c1 r0g0b0y0 p2", "p2", 30),
+            ("-:-:-:0/-:-:-:0 1 c1 r0g0b0y0 x", "x", 30),
+            ("-:-:-:0/-:-:-:0 1 c1 r0g0b0y0 x3", "x3", 30),
+        ];
+        for (notation, token, offset) in errors.iter() {
+            assert!(matches!(Game::try_from(*notation).unwrap_err(), Error::InvalidNotation { token: found, offset: at } if found == *token && at == *offset), "{}", notation);
+        }
+    }
+
+    #[test]
+    fn game_parse_lenient() -> Result<(), Error> {
+        let notation = "-:-:-:3/-:rrgg:c1,c2:5 1 -/c4 r0g0b2y1 p1";
+        let lenient = " -:-:-:3/-:RRGG:C1,c2:5\t1  -/C4\nR0G0V2Y1 P1 ";
+        assert_eq!(Game::parse(lenient, Mode::Lenient).unwrap(), Game::try_from(notation)?);
+        assert!(Game::parse(lenient, Mode::Strict).is_err());
+        let diagnostics = Game::parse("-:-:-:3/-:rq:c1,c99:x 3 -/c4,c1,c0 r0g0b2 p9", Mode::Strict).unwrap_err();
+        let spans: Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.span.clone()).collect();
+        assert_eq!(spans, vec![11..12, 16..19, 20..21, 22..23, 32..34, 41..41, 42..44]);
+        assert_eq!(diagnostics[1].message, "expected cards of the deck such as c3,c17, or -, found 'c1,c99'");
+        assert_eq!(diagnostics[4].message, "expected a pile of cards of the deck such as c5,c9,c45, or -, found 'c4,c1,c0'");
+        Ok(())
+    }
+
+    #[test]
+    fn action_notation() -> Result<(), Error> {
+        let take = Action::try_from("T 2 -rb")?;
+        assert_eq!(take, Action::Take(1, vec![Color::Red, Color::Blue]));
+        assert_eq!(take.to_notation(), "T 2 -rb");
+        assert_eq!(Action::try_from("T 4")?, Action::Take(3, vec![]));
+        assert_eq!(Action::Take(3, vec![]).to_string(), "T 4");
+        let play = Action::try_from("P c17 g1k4 b2j3")?;
+        assert_eq!(play, Action::Play(17, [Board::interpret("g1k4")?, Board::interpret("b2j3")?]));
+        assert_eq!(play.to_notation(), "P c17 g1k4 b2j3");
+        assert_eq!(Action::try_from("D c17")?, Action::Discard(17));
+        assert_eq!(Action::Discard(17).to_notation(), "D c17");
+        let mut game = unshuffled(2)?;
+        for action in game.legal_actions().into_iter().take(3) {
+            assert_eq!(Action::try_from(action.to_notation().as_str())?, action);
+        }
+        game.apply(Action::Take(0, vec![]))?;
+        game.apply(Action::Take(1, vec![]))?;
+        for action in game.legal_actions() {
+            assert_eq!(Action::try_from(action.to_notation().as_str())?, action);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn action_notation_errors() {
+        let errors = [
+            ("P c17 b2j3 g1z4", "z4", 13),
+            ("P c0 b2j3 g1k4", "c0", 2),
+            ("P c17 b2j3", "P c17 b2j3", 0),
+            ("T 0", "0", 2),
+            ("T x", "x", 2),
+            ("T 1 rb", "rb", 4),
+            ("T 1 -rx", "x", 6),
+            ("T 1 -r -b", "T 1 -r -b", 0),
+            ("D 17", "17", 2),
+            ("D c17 c18", "D c17 c18", 0),
+            ("X 1", "X", 0),
+            ("", "", 0),
+        ];
+        for (notation, token, offset) in errors.iter() {
+            assert!(matches!(Action::try_from(*notation).unwrap_err(), Error::InvalidNotation { token: found, offset: at } if found == *token && at == *offset), "{}", notation);
+        }
+    }
+
+    #[test]
+    fn action_parse() -> Result<(), Error> {
+        assert_eq!(Action::parse(" t 2, -RV ", Mode::Lenient), Ok(Action::try_from("T 2 -rb")?));
+        assert_eq!(Action::parse("p C17 v2j3,G1K4", Mode::Lenient), Ok(Action::try_from("P c17 b2j3 g1k4")?));
+        assert!(Action::parse("t 2", Mode::Strict).is_err());
+        let diagnostics = Action::parse("P c17 x2j3 g5k9", Mode::Strict).unwrap_err();
+        let messages: Vec<String> = diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect();
+        assert_eq!(messages, vec![
+            "6..7: unknown colour 'x', expected r, g, b or y",
+            "12..13: tier must be between 1 and 4, found '5'",
+            "13..15: no position 'k9' on the board, expected i1 to l4",
+        ]);
+        let diagnostics = Action::parse("T 0 -rq", Mode::Strict).unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "expected a pile number starting at 1, found '0'");
+        Ok(())
+    }
+
+    #[test]
+    fn game_bytes() -> Result<(), Error> {
+        let mut game = Game::new(3, 11)?;
+        let start = game.to_bytes()?;
+        assert_eq!(&start[..2], &[b'G', crate::ENCODING_VERSION]);
+        assert_eq!(Game::from_bytes(&start)?, game);
+        for turn in 0..40 {
+            let actions = game.legal_actions();
+            game.apply(actions[turn * 7 % actions.len()].clone())?;
+            assert_eq!(Game::from_bytes(&game.to_bytes()?)?, game);
+        }
+        while !game.is_over() {
+            let action = game.legal_actions()[0].clone();
+            game.apply(action)?;
+        }
+        let restored = Game::from_bytes(&game.to_bytes()?)?;
+        assert!(restored.is_over());
+        assert_eq!(restored.end_reason(), game.end_reason());
+        assert_eq!(restored, game);
+        let deck = parse_deck("c60 Line(r) 2 rg\nc61 Surround(y,b) 5 ry\nc62 Shape(r2+@i1,?@j1,_@j2,mirror) 3 gg\n")?;
+        let custom = Game::parse_with_deck("r1i1:rg:c60:4/-:-:-:0 2 c61/c62 r25g25b26y26 -", &deck, Mode::Strict).unwrap();
+        let restored = Game::from_bytes(&custom.to_bytes()?)?;
+        assert_eq!(restored, custom);
+        assert_eq!(restored.players()[0].hand().cards(), &deck[..1]);
+        assert_eq!(restored.display().top(1).map(|card| card.to_notation()), Some(deck[2].to_notation()));
+        Ok(())
+    }
+
+    #[test]
+    fn game_bytes_errors() -> Result<(), Error> {
+        let bytes = Game::new(2, 11)?.to_bytes()?;
+        let offset = |bytes: &[u8]| match Game::from_bytes(bytes) {
+            Err(Error::InvalidEncoding(offset)) => Some(offset),
+            _ => None,
+        };
+        assert_eq!(offset(&bytes[..bytes.len() - 1]), Some(bytes.len() - 1));
+        assert_eq!(offset(&[bytes.as_slice(), &[0]].concat()), Some(bytes.len()));
+        let invalid = |index: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[index] = byte;
+            offset(&bytes)
+        };
+        // the supply counts follow the end, then every player with an empty
+        // hand takes the board key, the reserve, the hand size and the score
+        let players = END_OFFSET + 1 + 4;
+        let piles = players + 2 * (KEY_BYTES + 2 + 1 + 4);
+        assert_eq!(invalid(0, b'B'), Some(0));
+        assert_eq!(invalid(COUNT_OFFSET, 5), Some(COUNT_OFFSET));
+        assert_eq!(invalid(CURRENT_OFFSET, 2), Some(CURRENT_OFFSET));
+        assert_eq!(invalid(END_OFFSET, 0x15), Some(END_OFFSET));
+        assert_eq!(invalid(END_OFFSET, 0x32), Some(END_OFFSET));
+        // last byte of the board key of the first player
+        assert_eq!(invalid(players + KEY_BYTES - 1, 0x05), Some(players + KEY_BYTES - 1));
+        // the first card of the first pile, after the pile count and its
+        // size, starts with the length of its notation
+        let card = piles + 2;
+        assert_eq!(bytes[piles], PILES as u8);
+        assert_eq!(invalid(card + 2, b'x'), Some(card));
+        assert_eq!(invalid(card + 2, 0xff), Some(card));
+        assert_eq!(invalid(card + 1, 0xff), Some(bytes.len()));
+        let first = placeholder_deck()[0].clone();
+        let repeated = Game::with_display(2, CardDisplay::from_piles(vec![vec![first.clone(), first]]))?.to_bytes()?;
+        let length = repeated.len() - piles - 2;
+        assert_eq!(offset(&repeated), Some(piles + 2 + length / 2));
+        Ok(())
+    }
+
+    #[test]
+    fn game_bytes_limits() -> Result<(), Error> {
+        let card = placeholder_deck()[0].clone();
+        let game = Game::with_display(2, CardDisplay::from_piles(vec![vec![card.clone()]; 15]))?;
+        assert!(game.to_bytes().is_ok());
+        let game = Game::with_display(2, CardDisplay::from_piles(vec![vec![card.clone()]; 16]))?;
+        assert!(matches!(game.to_bytes(), Err(Error::OutOfBounds)));
+        let game = Game::with_display(2, CardDisplay::from_piles(vec![vec![card; 256]]))?;
+        assert!(matches!(game.to_bytes(), Err(Error::OutOfBounds)));
+        Ok(())
+    }
+}
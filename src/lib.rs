@@ -1,14 +1,52 @@
 #[macro_use] extern crate maplit;
 
 mod board;
-pub use crate::board::{Board, Position};
+pub use crate::board::{bits_mask, mask_bits, Board, Mask, MaskSet, Position};
 
 mod card;
+pub use crate::card::{deck_notation, parse_deck, placeholder_deck, Card};
+
+mod display;
+pub use crate::display::{CardDisplay, Rng, PILES};
+
+mod game;
+pub use crate::game::{Action, EndReason, Game};
+
+mod encoding;
+pub use crate::encoding::ENCODING_VERSION;
 
 mod error;
-pub use crate::error::Error;
+pub use crate::error::{Error, Reason};
+
+mod notation;
+pub use crate::notation::{tokenize, Diagnostic, Mode, Token};
 
 mod pattern;
+pub use crate::pattern::{
+    parse_pattern, AdjacentColors, AdjacentT2, Both, Completion, Corner, Diagonal, DiagonalStacks, Either, Except,
+    Line, Pattern, Square, Surround,
+};
+
+mod predicate;
+pub use crate::predicate::{parse_predicate, And, Anything, Bound, Cell, Colors, Empty, Not, Or, Predicate};
+
+mod shape;
+pub use crate::shape::{parse_shape_grid, ShapePattern};
+
+mod player;
+pub use crate::player::{Hand, Player, Reserve};
 
 mod pieces;
 pub use crate::pieces::{Color, Tier, Stack};
+
+mod record;
+pub use crate::record::{Headers, Move, Record, RecordReader};
+
+mod result;
+pub use crate::result::{GameResult, Standing};
+
+#[cfg(feature = "serde")]
+mod serialize;
+
+mod supply;
+pub use crate::supply::{Supply, PIECES_PER_COLOR};
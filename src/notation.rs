@@ -0,0 +1,366 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use crate::{Card, Color, Error, Position, Stack, Tier, pieces::TierLevel};
+
+/// How strictly notation is read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Canonical notation only: lower-case letters and tokens separated by
+    /// single spaces.
+    Strict,
+    /// Tokens separated by any whitespace and commas, letters in any case
+    /// and `v` (violet, as on the physical pieces) standing for blue.
+    Lenient,
+}
+
+/// Piece of notation together with its byte range in the whole notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub span: Range<usize>,
+}
+
+/// Problem found in notation, pointing at the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(span: Range<usize>, message: String) -> Diagnostic {
+        Diagnostic { span, message }
+    }
+
+    /// Notation error for the offending text of the given notation.
+    pub fn to_error(&self, notation: &str) -> Error {
+        Error::notation(notation.get(self.span.clone()).unwrap_or(""), self.span.start)
+    }
+}
+
+impl Diagnostic {
+    /// Diagnostic pointing at the offending token of a notation error.
+    pub(crate) fn from_error(error: &Error, message: String) -> Diagnostic {
+        let span = match error {
+            Error::InvalidNotation { token, offset } => *offset..offset + token.len(),
+            _ => 0..0,
+        };
+        Diagnostic::new(span, message)
+    }
+
+    /// Same diagnostic for the notation embedded at the given offset.
+    pub(crate) fn shifted(self, by: usize) -> Diagnostic {
+        Diagnostic::new(self.span.start + by..self.span.end + by, self.message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}: {}", self.span.start, self.span.end, self.message)
+    }
+}
+
+/// Split notation into tokens.
+///
+/// In strict mode, tokens are separated by single spaces and any other
+/// separator is reported. In lenient mode, tokens are separated by any run
+/// of whitespace and commas, including leading and trailing ones.
+pub fn tokenize(notation: &str, mode: Mode) -> (Vec<Token<'_>>, Vec<Diagnostic>) {
+    let mut tokens = Vec::new();
+    let mut diagnostics = Vec::new();
+    match mode {
+        Mode::Strict if notation.is_empty() => {},
+        Mode::Strict => {
+            let mut start = 0;
+            for text in notation.split(' ') {
+                let span = start..start + text.len();
+                start = span.end + 1;
+                if text.is_empty() {
+                    let message = "expected a token, tokens are separated by a single space";
+                    diagnostics.push(Diagnostic::new(span, message.to_string()));
+                } else if let Some((index, letter)) = text.char_indices().find(|(_, letter)| letter.is_whitespace()) {
+                    let span = span.start + index..span.start + index + letter.len_utf8();
+                    let message = format!("unexpected {:?}, tokens are separated by a single space", letter);
+                    diagnostics.push(Diagnostic::new(span, message));
+                } else {
+                    tokens.push(Token { text, span });
+                }
+            }
+        },
+        Mode::Lenient => tokens = separated(notation, |letter| letter.is_whitespace() || letter == ','),
+    }
+    (tokens, diagnostics)
+}
+
+/// Split notation whose tokens may contain commas, such as a card or a game
+/// state, into tokens.
+///
+/// Same as `tokenize`, except that in lenient mode tokens are separated by
+/// runs of whitespace only.
+pub(crate) fn words(notation: &str, mode: Mode) -> (Vec<Token<'_>>, Vec<Diagnostic>) {
+    match mode {
+        Mode::Strict => tokenize(notation, mode),
+        Mode::Lenient => (separated(notation, char::is_whitespace), Vec::new()),
+    }
+}
+
+/// Tokens separated by any run of separators.
+fn separated(notation: &str, separator: impl Fn(char) -> bool) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, letter) in notation.char_indices().chain(std::iter::once((notation.len(), ' '))) {
+        let separator = separator(letter) || index == notation.len();
+        match start {
+            None if !separator => start = Some(index),
+            Some(begin) if separator => {
+                tokens.push(Token { text: &notation[begin..index], span: begin..index });
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    tokens
+}
+
+/// Text as the canonical notation writes it: in lenient mode, with letters
+/// in lower case and `v` for blue written `b`.
+///
+/// Every letter keeps its byte length, so offsets into the text hold for
+/// the original notation too.
+pub(crate) fn normalized(text: &str, mode: Mode) -> String {
+    match mode {
+        Mode::Strict => text.to_string(),
+        Mode::Lenient => text.chars().map(|letter| match canonical(letter, mode) {
+            'v' => 'b',
+            letter => letter,
+        }).collect(),
+    }
+}
+
+/// Split a notation into parts, each with its byte offset in the whole notation.
+pub(crate) fn split(notation: &str, separator: char, offset: usize) -> Vec<(usize, &str)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for part in notation.split(separator) {
+        parts.push((offset + start, part));
+        start += part.len() + separator.len_utf8();
+    }
+    parts
+}
+
+/// Two placements of a play.
+type Placements = [(Position, Stack); 2];
+
+/// Letters of a token, each with its span.
+fn letters(token: &Token) -> Vec<(Range<usize>, char)> {
+    token.text
+        .char_indices()
+        .map(|(index, letter)| (token.span.start + index..token.span.start + index + letter.len_utf8(), letter))
+        .collect()
+}
+
+/// Letter as the canonical notation writes it.
+fn canonical(letter: char, mode: Mode) -> char {
+    match mode {
+        Mode::Strict => letter,
+        Mode::Lenient => letter.to_ascii_lowercase(),
+    }
+}
+
+pub(crate) fn color((span, letter): &(Range<usize>, char), mode: Mode) -> Result<Color, Diagnostic> {
+    match (canonical(*letter, mode), mode) {
+        ('r', _) => Ok(Color::Red),
+        ('g', _) => Ok(Color::Green),
+        ('b', _) | ('v', Mode::Lenient) => Ok(Color::Blue),
+        ('y', _) => Ok(Color::Yellow),
+        _ => Err(Diagnostic::new(span.clone(), format!("unknown colour '{}', expected r, g, b or y", letter))),
+    }
+}
+
+fn tier((span, letter): &(Range<usize>, char)) -> Result<Tier, Diagnostic> {
+    match letter {
+        '1' => Ok(Tier::First),
+        '2' => Ok(Tier::Second),
+        '3' => Ok(Tier::Third),
+        '4' => Ok(Tier::Fourth),
+        _ => Err(Diagnostic::new(span.clone(), format!("tier must be between 1 and 4, found '{}'", letter))),
+    }
+}
+
+fn position(file: &(Range<usize>, char), rank: &(Range<usize>, char), mode: Mode) -> Result<Position, Diagnostic> {
+    let notation: String = [canonical(file.1, mode), rank.1].iter().collect();
+    Position::try_from(notation.as_str()).map_err(|_| {
+        let message = format!("no position '{}{}' on the board, expected i1 to l4", file.1, rank.1);
+        Diagnostic::new(file.0.start..rank.0.end, message)
+    })
+}
+
+/// Parse a placement such as `r1i1`, reporting every wrong part of it.
+pub(crate) fn placement(token: &Token, mode: Mode) -> Result<(Position, Stack), Vec<Diagnostic>> {
+    let letters = letters(token);
+    if letters.len() != 4 {
+        let message = format!("expected a placement such as r1i1, found '{}'", token.text);
+        return Err(vec![Diagnostic::new(token.span.clone(), message)]);
+    }
+    match (color(&letters[0], mode), tier(&letters[1]), position(&letters[2], &letters[3], mode)) {
+        (Ok(color), Ok(tier), Ok(position)) => Ok((position, Stack { color, tier })),
+        (color, tier, position) => Err(vec![color.err(), tier.err(), position.err()].into_iter().flatten().collect()),
+    }
+}
+
+/// Parse a placement that may list the colours of the whole stack from the
+/// bottom up, such as `rgb3j3`, instead of only its top colour.
+///
+/// Besides the placement, gives the colours beneath the top, which are empty
+/// when only the top colour is listed.
+pub(crate) fn layered_placement(token: &Token, mode: Mode) -> Result<(Position, Stack, Vec<Color>), Vec<Diagnostic>> {
+    let letters = letters(token);
+    if letters.len() <= 4 {
+        return placement(token, mode).map(|(position, stack)| (position, stack, Vec::new()));
+    }
+    let (layers, rest) = letters.split_at(letters.len() - 3);
+    if !layers.iter().all(|(_, letter)| letter.is_alphabetic()) {
+        let message = format!("expected a placement such as r1i1 or rg2i1, found '{}'", token.text);
+        return Err(vec![Diagnostic::new(token.span.clone(), message)]);
+    }
+    let colors: Vec<Result<Color, Diagnostic>> = layers.iter().map(|letter| color(letter, mode)).collect();
+    let tier = tier(&rest[0]);
+    let position = position(&rest[1], &rest[2], mode);
+    let mut diagnostics: Vec<Diagnostic> = colors.iter().filter_map(|color| color.clone().err()).collect();
+    diagnostics.extend(tier.clone().err());
+    diagnostics.extend(position.clone().err());
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+    let (mut colors, tier): (Vec<Color>, Tier) = (colors.into_iter().flatten().collect(), tier.unwrap());
+    if colors.len() != tier.level() as usize {
+        let span = layers[0].0.start..layers[layers.len() - 1].0.end;
+        let message = format!("a stack of tier {} has {} colours, found {}", tier, tier.level(), colors.len());
+        return Err(vec![Diagnostic::new(span, message)]);
+    }
+    let color = colors.pop().expect("stack has a top colour");
+    Ok((position.unwrap(), Stack { color, tier }, colors))
+}
+
+/// Parse the two pieces of a card, such as `bg`.
+pub(crate) fn pieces(token: &Token, mode: Mode) -> Result<[Color; 2], Vec<Diagnostic>> {
+    let letters = letters(token);
+    if letters.len() != 2 {
+        let message = format!("expected two pieces such as bg, found '{}'", token.text);
+        return Err(vec![Diagnostic::new(token.span.clone(), message)]);
+    }
+    match (color(&letters[0], mode), color(&letters[1], mode)) {
+        (Ok(first), Ok(second)) => Ok([first, second]),
+        (first, second) => Err(first.err().into_iter().chain(second.err()).collect()),
+    }
+}
+
+/// Parse the pieces discarded on a take, such as `-rb`.
+pub(crate) fn discard(token: &Token, mode: Mode) -> Result<Vec<Color>, Vec<Diagnostic>> {
+    let letters = letters(token);
+    if letters.len() < 2 || letters[0].1 != '-' {
+        let message = format!("expected discarded pieces such as -rb, found '{}'", token.text);
+        return Err(vec![Diagnostic::new(token.span.clone(), message)]);
+    }
+    let colors: Vec<Result<Color, Diagnostic>> = letters[1..].iter().map(|letter| color(letter, mode)).collect();
+    if colors.iter().any(|color| color.is_err()) {
+        return Err(colors.into_iter().filter_map(|color| color.err()).collect());
+    }
+    Ok(colors.into_iter().flatten().collect())
+}
+
+/// Parse a card reference such as `c17` into the card identifier.
+pub(crate) fn reference(token: &Token, mode: Mode) -> Result<u8, Diagnostic> {
+    let reference: String = token.text.chars().map(|letter| canonical(letter, mode)).collect();
+    Card::parse_reference(&reference).map_err(|_| {
+        Diagnostic::new(token.span.clone(), format!("expected a card reference such as c17, found '{}'", token.text))
+    })
+}
+
+/// Parse the card reference and the two placements of a play.
+///
+/// Whether the placements use the pieces of the card depends on the deck of
+/// the game, so it is checked when the play is applied.
+pub(crate) fn play(
+    card: &Token,
+    first: &Token,
+    second: &Token,
+    mode: Mode,
+) -> Result<(u8, Placements), Vec<Diagnostic>> {
+    match (reference(card, mode), placement(first, mode), placement(second, mode)) {
+        (Ok(id), Ok(a), Ok(b)) => Ok((id, [a, b])),
+        (id, a, b) => {
+            let mut diagnostics: Vec<Diagnostic> = id.err().into_iter().collect();
+            diagnostics.extend(a.err().into_iter().flatten());
+            diagnostics.extend(b.err().into_iter().flatten());
+            Err(diagnostics)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts<'a>(tokens: &[Token<'a>]) -> Vec<&'a str> {
+        tokens.iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn tokenize_strict() {
+        let (tokens, diagnostics) = tokenize("r1i1 g2j2", Mode::Strict);
+        assert_eq!(texts(&tokens), vec!["r1i1", "g2j2"]);
+        assert_eq!(tokens[1].span, 5..9);
+        assert!(diagnostics.is_empty());
+        let (tokens, diagnostics) = tokenize(" r1i1  g2j2\t", Mode::Strict);
+        assert_eq!(texts(&tokens), vec!["r1i1"]);
+        let spans: Vec<Range<usize>> = diagnostics.iter().map(|diagnostic| diagnostic.span.clone()).collect();
+        assert_eq!(spans, vec![0..0, 6..6, 11..12]);
+        assert_eq!(diagnostics[2].to_string(), "11..12: unexpected '\\t', tokens are separated by a single space");
+        assert_eq!(tokenize("", Mode::Strict), (vec![], vec![]));
+    }
+
+    #[test]
+    fn tokenize_lenient() {
+        let (tokens, diagnostics) = tokenize(" R1I1,\tg2j2 ,\n", Mode::Lenient);
+        assert_eq!(texts(&tokens), vec!["R1I1", "g2j2"]);
+        assert_eq!(tokens[0].span, 1..5);
+        assert_eq!(tokens[1].span, 7..11);
+        assert!(diagnostics.is_empty());
+        assert!(tokenize(" ,, ", Mode::Lenient).0.is_empty());
+    }
+
+    #[test]
+    fn placement_diagnostics() {
+        let (tokens, _) = tokenize("x5z9 V2J3 r1i1x", Mode::Lenient);
+        let diagnostics = placement(&tokens[0], Mode::Lenient).unwrap_err();
+        let messages: Vec<String> = diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect();
+        assert_eq!(messages, vec![
+            "0..1: unknown colour 'x', expected r, g, b or y",
+            "1..2: tier must be between 1 and 4, found '5'",
+            "2..4: no position 'z9' on the board, expected i1 to l4",
+        ]);
+        assert_eq!(placement(&tokens[1], Mode::Lenient), Ok((Position::j3, Stack { color: Color::Blue, tier: Tier::Second })));
+        assert!(placement(&tokens[1], Mode::Strict).is_err());
+        assert_eq!(placement(&tokens[2], Mode::Lenient).unwrap_err()[0].span, 10..15);
+    }
+
+    #[test]
+    fn layered_placement_diagnostics() {
+        let (tokens, _) = tokenize("rgb3j3 b3j3 RV2k1 rg3j3 rx2z9 r11i1", Mode::Lenient);
+        let stack = |notation| Stack::try_from(notation).unwrap();
+        assert_eq!(layered_placement(&tokens[0], Mode::Strict), Ok((Position::j3, stack("b3"), vec![Color::Red, Color::Green])));
+        assert_eq!(layered_placement(&tokens[1], Mode::Strict), Ok((Position::j3, stack("b3"), vec![])));
+        assert_eq!(layered_placement(&tokens[2], Mode::Lenient), Ok((Position::k1, stack("b2"), vec![Color::Red])));
+        let messages = |token| -> Vec<String> {
+            layered_placement(token, Mode::Strict).unwrap_err().iter().map(|diagnostic| diagnostic.to_string()).collect()
+        };
+        assert_eq!(messages(&tokens[3]), vec!["18..20: a stack of tier 3 has 3 colours, found 2"]);
+        assert_eq!(messages(&tokens[4]), vec![
+            "25..26: unknown colour 'x', expected r, g, b or y",
+            "27..29: no position 'z9' on the board, expected i1 to l4",
+        ]);
+        assert_eq!(messages(&tokens[5]), vec!["30..35: expected a placement such as r1i1 or rg2i1, found 'r11i1'"]);
+    }
+}
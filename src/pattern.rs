@@ -1,28 +1,121 @@
-use std::option::Option;
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::{Arc, OnceLock};
 use strum::IntoEnumIterator;
 
-use crate::{Board, Color, board::{Mask, MaskSet}, Position, Tier, Stack};
+use crate::{Board, Color, board::{mask_bits, Mask, MaskSet}, Error, Position, Tier, Stack};
+use crate::card::disjoint_masks;
+use crate::notation::{normalized, Mode};
+use crate::predicate::{parse_predicate, Bound, Cell};
+use crate::shape::ShapePattern;
+
+/// Placements of the two pieces of a card that would score more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Placements in the order they are made, as in `Action::Play`.
+    pub placements: [(Position, Stack); 2],
+    /// Number of disjoint fits the placements add for a pattern, or points
+    /// they add for a card.
+    pub gain: u32,
+}
 
 pub trait Pattern {
+    /// Name of the pattern followed by its arguments, e.g. `Surround(y,b)`.
+    ///
+    /// See `parse_pattern` for the reverse. Defaults to the type name, which
+    /// `parse_pattern` doesn't know, so patterns defined outside this crate
+    /// have to override it to be read back from a deck file.
+    fn to_notation(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
     fn fit(&self, board: &Board) -> MaskSet {
         let mut fits = MaskSet::new();
         for position in Position::iter() {
-            if let Some(mask) = self.fit_at(&position, &board) {
+            if let Some(mask) = self.fit_at(&position, board) {
                 fits.insert(mask);
             }
-            if let Some(mask) = self.fit_at_90deg(&position, &board) {
+            if let Some(mask) = self.fit_at_90deg(&position, board) {
                 fits.insert(mask);
             }
-            if let Some(mask) = self.fit_at_180deg(&position, &board) {
+            if let Some(mask) = self.fit_at_180deg(&position, board) {
                 fits.insert(mask);
             }
-            if let Some(mask) = self.fit_at_270deg(&position, &board) {
+            if let Some(mask) = self.fit_at_270deg(&position, board) {
                 fits.insert(mask);
             }
         }
         fits
     }
 
+    /// Fits as 16-bit board masks, see `Position::bit`, in ascending order.
+    ///
+    /// Gives the same fits as `fit`. Patterns with a bitboard backend find
+    /// them with a few bitwise operations on the masks of `Board` instead of
+    /// looking up every position.
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        let mut fits: Vec<u16> = self.fit(board).iter().map(mask_bits).collect();
+        fits.sort_unstable();
+        fits
+    }
+
+    /// Number of times the pattern scores on a board.
+    ///
+    /// Every piece may be counted only once, so this is the largest number
+    /// of non-overlapping fits.
+    fn count(&self, board: &Board) -> usize {
+        disjoint_masks(&self.fit_bits(board))
+    }
+
+    /// Every pair of placements of the two pieces that would raise the
+    /// number of times the pattern scores, see `count`, with the most gained
+    /// first.
+    ///
+    /// Placements leading to the same board are listed only once. A piece
+    /// may be placed on top of the other one. The placements are tried out
+    /// on a single copy of the stacks, without the layers beneath them.
+    fn completions(&self, board: &Board, pieces: [Color; 2]) -> Vec<Completion> {
+        let before = self.count(board);
+        let orders = if pieces[0] == pieces[1] { vec![pieces] } else { vec![pieces, [pieces[1], pieces[0]]] };
+        let mut seen = HashSet::new();
+        let mut completions = Vec::new();
+        let mut probe = board.probe();
+        let restore = |probe: &mut Board, position, covered: Option<Stack>| match covered {
+            Some(stack) => probe.put(position, stack),
+            None => probe.remove(position),
+        };
+        for [a, b] in orders {
+            for first in Position::iter() {
+                let first_stack = match probe.placement(first, a) {
+                    Some(stack) => stack,
+                    None => continue,
+                };
+                let first_covered = probe.put(first, first_stack);
+                for second in Position::iter() {
+                    let second_stack = match probe.placement(second, b) {
+                        Some(stack) => stack,
+                        None => continue,
+                    };
+                    let second_covered = probe.put(second, second_stack);
+                    if seen.insert(probe.masks()) {
+                        let after = self.count(&probe);
+                        if after > before {
+                            completions.push(Completion {
+                                placements: [(first, first_stack), (second, second_stack)],
+                                gain: (after - before) as u32,
+                            });
+                        }
+                    }
+                    restore(&mut probe, second, second_covered);
+                }
+                restore(&mut probe, first, first_covered);
+            }
+        }
+        completions.sort_by_key(|completion| std::cmp::Reverse(completion.gain));
+        completions
+    }
+
     fn fit_at(&self, _position: &Position, _board: &Board) -> Option<Mask> {
         None
     }
@@ -38,8 +131,16 @@ pub trait Pattern {
 }
 
 impl Pattern for Color {
+    fn to_notation(&self) -> String {
+        format!("Color({})", self)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        single_bits(board.color_mask(*self))
+    }
+
     fn fit_at(&self, position: &Position, board: &Board) -> Option<Mask> {
-        if let Some(stack) = board.get(&position) {
+        if let Some(stack) = board.get(position) {
             if stack.color == *self {
                 return Some(btreeset!{*position});
             }
@@ -49,8 +150,16 @@ impl Pattern for Color {
 }
 
 impl Pattern for Tier {
+    fn to_notation(&self) -> String {
+        format!("Tier({})", self)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        single_bits(board.tier_mask(*self))
+    }
+
     fn fit_at(&self, position: &Position, board: &Board) -> Option<Mask> {
-        if let Some(stack) = board.get(&position) {
+        if let Some(stack) = board.get(position) {
             if stack.tier == *self {
                 return Some(btreeset!{*position});
             }
@@ -60,8 +169,16 @@ impl Pattern for Tier {
 }
 
 impl Pattern for Stack {
+    fn to_notation(&self) -> String {
+        format!("Stack({})", self)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        single_bits(board.color_mask(self.color) & board.tier_mask(self.tier))
+    }
+
     fn fit_at(&self, position: &Position, board: &Board) -> Option<Mask> {
-        if let Some(stack) = board.get(&position) {
+        if let Some(stack) = board.get(position) {
             if stack == self {
                 return Some(btreeset!{*position});
             }
@@ -70,115 +187,85 @@ impl Pattern for Stack {
     }
 }
 
-pub struct AdjacentColors(Color, Color);
+pub struct AdjacentColors(pub Color, pub Color);
 
 impl AdjacentColors {
-    fn fit_colors(&self, pos1: &Position, pos2: &Position, board: &Board) -> Option<Mask> {
-        let stack1 = board.get(&pos1);
-        let stack2 = board.get(&pos2);
-        if let (Some(stack1), Some(stack2)) = (stack1, stack2) {
-            let fit = stack1.color == self.0 && stack2.color == self.1;
-            let fit_inv = stack1.color == self.1 && stack2.color == self.0;
-            if fit || fit_inv {
-                return Some(btreeset!{*pos1, *pos2});
-            }
-        }
-        None
+    /// Stacks of the two colours side by side.
+    pub fn shape(&self) -> ShapePattern {
+        ShapePattern::new(vec![(Position::i1, Arc::new(self.0)), (Position::j1, Arc::new(self.1))])
     }
 }
 
 impl Pattern for AdjacentColors {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.right() {
-            self.fit_colors(pos1, &pos2, &board)
-        } else {
-            None
-        }
+    fn to_notation(&self) -> String {
+        format!("AdjacentColors({},{})", self.0, self.1)
     }
 
-    fn fit_at_90deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.up() {
-            self.fit_colors(pos1, &pos2, &board)
-        } else {
-            None
-        }
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::AdjacentColors.shape(self.0, self.1).fit(board)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        Builtin::AdjacentColors.shape(self.0, self.1).fit_bits(board)
     }
 }
 
-pub struct AdjacentT2(Color);
+pub struct AdjacentT2(pub Color);
 
 impl AdjacentT2 {
-    fn fit_t2_color(&self, pos1: &Position, pos2: &Position, board: &Board) -> Option<Mask> {
-        let stack1 = board.get(&pos1);
-        let stack2 = board.get(&pos2);
-        if let (Some(stack1), Some(stack2)) = (stack1, stack2) {
-            let stack_fit = stack1.tier == Tier::Second && stack2.tier == Tier::Second;
-            let color_fit = stack1.color == self.0 && stack2.color == self.0;
-            if stack_fit && color_fit {
-                return Some(btreeset!{*pos1, *pos2});
-            }
-        }
-        None
+    /// Two stacks of two pieces of the colour side by side.
+    pub fn shape(&self) -> ShapePattern {
+        let stack = Stack { color: self.0, tier: Tier::Second };
+        ShapePattern::uniform(Arc::new(stack), &[Position::i1, Position::j1])
     }
 }
 
 impl Pattern for AdjacentT2 {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.right() {
-            self.fit_t2_color(pos1, &pos2, &board)
-        } else {
-            None
-        }
+    fn to_notation(&self) -> String {
+        format!("AdjacentT2({})", self.0)
     }
 
-    fn fit_at_90deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.up() {
-            self.fit_t2_color(pos1, &pos2, &board)
-        } else {
-            None
-        }
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::AdjacentT2.shape(self.0, self.0).fit(board)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        let stacks = board.color_mask(self.0) & board.tier_mask(Tier::Second);
+        fit_uniform(Builtin::AdjacentT2.placements(), stacks)
     }
 }
 
-pub struct DiagonalStacks(Color, Color);
+pub struct DiagonalStacks(pub Color, pub Color);
 
 impl DiagonalStacks {
-    fn fit_diag_stacks(&self, pos1: &Position, pos2: &Position, board: &Board) -> Option<Mask> {
-        let stack1 = board.get(&pos1);
-        let stack2 = board.get(&pos2);
-        if let (Some(stack1), Some(stack2)) = (stack1, stack2) {
-            let stack_fit = stack1.tier >= Tier::Second && stack2.tier >= Tier::Second;
-            let color_fit = stack1.color == self.0 && stack2.color == self.1;
-            let color_fit_inv = stack1.color == self.1 && stack2.color == self.0;
-            if stack_fit && (color_fit || color_fit_inv) {
-                return Some(btreeset!{*pos1, *pos2});
-            }
-        }
-        None
+    /// Stacks of at least two pieces of the two colours diagonally.
+    pub fn shape(&self) -> ShapePattern {
+        let cell = |color| Arc::new(Cell { color: Some(color), tier: Some(Tier::Second), bound: Bound::AtLeast });
+        ShapePattern::new(vec![(Position::i1, cell(self.0)), (Position::j2, cell(self.1))])
     }
 }
 
 impl Pattern for DiagonalStacks {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.upright() {
-            self.fit_diag_stacks(pos1, &pos2, &board)
-        } else {
-            None
-        }
+    fn to_notation(&self) -> String {
+        format!("DiagonalStacks({},{})", self.0, self.1)
     }
 
-    fn fit_at_90deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.downright() {
-            self.fit_diag_stacks(pos1, &pos2, &board)
-        } else {
-            None
-        }
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::DiagonalStacks.shape(self.0, self.1).fit(board)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        Builtin::DiagonalStacks.shape(self.0, self.1).fit_bits(board)
     }
 }
 
-pub struct Surround(Color, Color);
+pub struct Surround(pub Color, pub Color);
 
 impl Pattern for Surround {
+    fn to_notation(&self) -> String {
+        format!("Surround({},{})", self.0, self.1)
+    }
+
     fn fit(&self, board: &Board) -> MaskSet {
         let mut bases = Mask::new();
         let mut highest = Tier::First;
@@ -202,7 +289,14 @@ impl Pattern for Surround {
                 fits.insert(mask);
             }
         }
-        return fits;
+        fits
+    }
+
+    /// Stacks of the second colour around the tallest stack of the first
+    /// colour, diagonals included. When stacks tie for the tallest, only the
+    /// one with the most such neighbours scores.
+    fn count(&self, board: &Board) -> usize {
+        self.fit(board).iter().map(|mask| mask.len()).max().unwrap_or(0)
     }
 
     fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
@@ -221,16 +315,14 @@ impl Pattern for Surround {
                 pos1.upright(),
             };
             let mut fits = Mask::new();
-            for pos in surrounding.iter() {
-                if let Some(pos) = pos {
-                    if let Some(stack) = board.get(&pos) {
-                        if stack.color == self.1 {
-                            fits.insert(*pos);
-                        }
+            for pos in surrounding.iter().flatten() {
+                if let Some(stack) = board.get(pos) {
+                    if stack.color == self.1 {
+                        fits.insert(*pos);
                     }
                 }
             }
-            if fits.len() > 0 {
+            if !fits.is_empty() {
                 return Some(fits);
             } else {
                 return None;
@@ -240,127 +332,343 @@ impl Pattern for Surround {
     }
 }
 
-trait Shape {
-    fn fit_mask(&self, mask: Mask, board: &Board) -> Option<Mask>;
-}
+pub struct Diagonal(pub Color);
 
-impl Shape for Color {
-    fn fit_mask(&self, mask: Mask, board: &Board) -> Option<Mask> {
-        for position in mask.iter() {
-            if self.fit_at(position, board) == None {
-                return None;
-            }
-        }
-        Some(mask)
+impl Diagonal {
+    /// Three stacks of the colour in a diagonal line.
+    pub fn shape(&self) -> ShapePattern {
+        ShapePattern::uniform(Arc::new(self.0), &[Position::i1, Position::j2, Position::k3])
     }
 }
 
-pub struct Diagonal(Color);
-
 impl Pattern for Diagonal {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.upright() {
-            if let Some(pos3) = pos2.upright() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+    fn to_notation(&self) -> String {
+        format!("Diagonal({})", self.0)
     }
 
-    fn fit_at_90deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.downright() {
-            if let Some(pos3) = pos2.downright() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::Diagonal.shape(self.0, self.0).fit(board)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        fit_uniform(Builtin::Diagonal.placements(), board.color_mask(self.0))
     }
 }
 
-pub struct Line(Color);
+pub struct Line(pub Color);
+
+impl Line {
+    /// Three stacks of the colour in a straight line.
+    pub fn shape(&self) -> ShapePattern {
+        ShapePattern::uniform(Arc::new(self.0), &[Position::i1, Position::j1, Position::k1])
+    }
+}
 
 impl Pattern for Line {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.right() {
-            if let Some(pos3) = pos2.right() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+    fn to_notation(&self) -> String {
+        format!("Line({})", self.0)
     }
 
-    fn fit_at_90deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.up() {
-            if let Some(pos3) = pos2.up() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::Line.shape(self.0, self.0).fit(board)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        fit_uniform(Builtin::Line.placements(), board.color_mask(self.0))
     }
 }
 
-pub struct Corner(Color);
+pub struct Corner(pub Color);
 
-impl Pattern for Corner {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.up() {
-            if let Some(pos3) = pos2.right() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+impl Corner {
+    /// Three stacks of the colour bent at a right angle.
+    pub fn shape(&self) -> ShapePattern {
+        ShapePattern::uniform(Arc::new(self.0), &[Position::i1, Position::i2, Position::j2])
     }
+}
 
-    fn fit_at_90deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.left() {
-            if let Some(pos3) = pos2.up() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+impl Pattern for Corner {
+    fn to_notation(&self) -> String {
+        format!("Corner({})", self.0)
     }
 
-    fn fit_at_180deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.down() {
-            if let Some(pos3) = pos2.left() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::Corner.shape(self.0, self.0).fit(board)
     }
 
-    fn fit_at_270deg(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.right() {
-            if let Some(pos3) = pos2.down() {
-                let mask = btreeset!{*pos1, pos2, pos3};
-                return self.0.fit_mask(mask, board);
-            }
-        }
-        None
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        fit_uniform(Builtin::Corner.placements(), board.color_mask(self.0))
     }
 }
 
-pub struct Square(Color);
+pub struct Square(pub Color);
+
+impl Square {
+    /// Four stacks of the colour in a square.
+    pub fn shape(&self) -> ShapePattern {
+        ShapePattern::uniform(Arc::new(self.0), &[Position::i1, Position::i2, Position::j1, Position::j2])
+    }
+}
 
 impl Pattern for Square {
-    fn fit_at(&self, pos1: &Position, board: &Board) -> Option<Mask> {
-        if let Some(pos2) = pos1.up() {
-            if let Some(pos3) = pos2.right() {
-                if let Some(pos4) = pos1.right() {
-                    let mask = btreeset!{*pos1, pos2, pos3, pos4};
-                    return self.0.fit_mask(mask, board);
-                }
-            }
+    fn to_notation(&self) -> String {
+        format!("Square({})", self.0)
+    }
+
+    fn fit(&self, board: &Board) -> MaskSet {
+        Builtin::Square.shape(self.0, self.0).fit(board)
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        fit_uniform(Builtin::Square.placements(), board.color_mask(self.0))
+    }
+}
+
+/// Fits of two patterns at once, cells fitting the first pattern that fit
+/// the second one as well, e.g. `Both(Line(r),Shape(2+@i1,2+@j1,2+@k1))` for
+/// a line of red stacks at least two pieces high.
+///
+/// Like every combination of patterns, it scores for its disjoint fits, see
+/// `Pattern::count`, even when it combines a `Surround`.
+pub struct Both(pub Arc<dyn Pattern + Send + Sync>, pub Arc<dyn Pattern + Send + Sync>);
+
+impl Pattern for Both {
+    fn to_notation(&self) -> String {
+        format!("Both({},{})", self.0.to_notation(), self.1.to_notation())
+    }
+
+    fn fit(&self, board: &Board) -> MaskSet {
+        self.0.fit(board).intersection(&self.1.fit(board)).cloned().collect()
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        let others = self.1.fit_bits(board);
+        self.0.fit_bits(board).into_iter().filter(|fit| others.binary_search(fit).is_ok()).collect()
+    }
+}
+
+/// Fits of either of two patterns, e.g. `Either(Line(r),Diagonal(r))` for
+/// three red stacks in a straight or a diagonal line.
+pub struct Either(pub Arc<dyn Pattern + Send + Sync>, pub Arc<dyn Pattern + Send + Sync>);
+
+impl Pattern for Either {
+    fn to_notation(&self) -> String {
+        format!("Either({},{})", self.0.to_notation(), self.1.to_notation())
+    }
+
+    fn fit(&self, board: &Board) -> MaskSet {
+        self.0.fit(board).union(&self.1.fit(board)).cloned().collect()
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        let mut fits = self.0.fit_bits(board);
+        fits.extend(self.1.fit_bits(board));
+        fits.sort_unstable();
+        fits.dedup();
+        fits
+    }
+}
+
+/// Fits of the first pattern that are not fits of the second one, e.g.
+/// `Except(Line(r),Shape(1@i1,1@j1,1@k1))` for a line of red stacks not all
+/// of a single piece.
+pub struct Except(pub Arc<dyn Pattern + Send + Sync>, pub Arc<dyn Pattern + Send + Sync>);
+
+impl Pattern for Except {
+    fn to_notation(&self) -> String {
+        format!("Except({},{})", self.0.to_notation(), self.1.to_notation())
+    }
+
+    fn fit(&self, board: &Board) -> MaskSet {
+        self.0.fit(board).difference(&self.1.fit(board)).cloned().collect()
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        let others = self.1.fit_bits(board);
+        self.0.fit_bits(board).into_iter().filter(|fit| others.binary_search(fit).is_err()).collect()
+    }
+}
+
+/// Every set bit of a mask as a mask of its own, in ascending order.
+fn single_bits(mut bits: u16) -> Vec<u16> {
+    let mut masks = Vec::new();
+    while bits != 0 {
+        let bit = bits & bits.wrapping_neg();
+        masks.push(bit);
+        bits &= !bit;
+    }
+    masks
+}
+
+/// Built-in patterns drawn as shapes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Builtin {
+    AdjacentColors,
+    AdjacentT2,
+    DiagonalStacks,
+    Diagonal,
+    Line,
+    Corner,
+    Square,
+}
+
+/// Number of `Builtin` patterns.
+const BUILTINS: usize = 7;
+
+impl Builtin {
+    /// Shape of the pattern of the given colours, the second of which only
+    /// matters for patterns of two colours.
+    ///
+    /// Every shape is built on first use and then shared by all patterns of
+    /// the same kind and colours.
+    fn shape(self, first: Color, second: Color) -> &'static ShapePattern {
+        static SHAPES: [OnceLock<ShapePattern>; BUILTINS * 16] = [const { OnceLock::new() }; BUILTINS * 16];
+        SHAPES[self as usize * 16 + first as usize * 4 + second as usize].get_or_init(|| match self {
+            Builtin::AdjacentColors => AdjacentColors(first, second).shape(),
+            Builtin::AdjacentT2 => AdjacentT2(first).shape(),
+            Builtin::DiagonalStacks => DiagonalStacks(first, second).shape(),
+            Builtin::Diagonal => Diagonal(first).shape(),
+            Builtin::Line => Line(first).shape(),
+            Builtin::Corner => Corner(first).shape(),
+            Builtin::Square => Square(first).shape(),
+        })
+    }
+
+    /// Every placement of the shape as a 16-bit board mask, which does not
+    /// depend on the colours.
+    fn placements(self) -> &'static [u16] {
+        static PLACEMENTS: [OnceLock<Vec<u16>>; BUILTINS] = [const { OnceLock::new() }; BUILTINS];
+        PLACEMENTS[self as usize].get_or_init(|| self.shape(Color::Red, Color::Red).placement_masks())
+    }
+}
+
+/// Placement masks covered entirely by the stacks of a mask.
+fn fit_uniform(placements: &[u16], stacks: u16) -> Vec<u16> {
+    placements.iter().copied().filter(|placement| stacks & placement == *placement).collect()
+}
+
+/// Parse the notation of a pattern, e.g. `Line(r)` or `Surround(y,b)`.
+///
+/// Colours are given by their letters, a tier by its number and a stack
+/// in the usual notation, e.g. `Stack(r4)`. A shape lists the condition and
+/// position of every cell, optionally followed by `mirror`, see `ShapePattern`.
+/// `Both`, `Either` and `Except` combine the two patterns they are given.
+pub fn parse_pattern(notation: &str) -> Result<Arc<dyn Pattern + Send + Sync>, Error> {
+    let (name, rest) = notation.split_once('(').ok_or_else(|| Error::notation(notation, 0))?;
+    let offset = name.len() + 1;
+    let list = rest.strip_suffix(')').ok_or_else(|| Error::notation(rest, offset))?;
+    let arguments = arguments(list, offset);
+    let color = |index: usize| {
+        let (offset, argument) = arguments[index];
+        Color::try_from(argument).map_err(|error| error.shifted(offset))
+    };
+    let pattern = |index: usize| {
+        let (offset, argument) = arguments[index];
+        parse_pattern(argument).map_err(|error| error.shifted(offset))
+    };
+    if name == "Shape" {
+        return parse_shape(&arguments, list, offset).map(|shape| Arc::new(shape) as Arc<dyn Pattern + Send + Sync>);
+    }
+    let pattern: Arc<dyn Pattern + Send + Sync> = match (name, arguments.len()) {
+        ("Color", 1) => Arc::new(color(0)?),
+        ("Tier", 1) => Arc::new(Tier::try_from(arguments[0].1).map_err(|error| error.shifted(offset))?),
+        ("Stack", 1) => Arc::new(Stack::try_from(arguments[0].1).map_err(|error| error.shifted(offset))?),
+        ("AdjacentColors", 2) => Arc::new(AdjacentColors(color(0)?, color(1)?)),
+        ("AdjacentT2", 1) => Arc::new(AdjacentT2(color(0)?)),
+        ("DiagonalStacks", 2) => Arc::new(DiagonalStacks(color(0)?, color(1)?)),
+        ("Surround", 2) => Arc::new(Surround(color(0)?, color(1)?)),
+        ("Diagonal", 1) => Arc::new(Diagonal(color(0)?)),
+        ("Line", 1) => Arc::new(Line(color(0)?)),
+        ("Corner", 1) => Arc::new(Corner(color(0)?)),
+        ("Square", 1) => Arc::new(Square(color(0)?)),
+        ("Both", 2) => Arc::new(Both(pattern(0)?, pattern(1)?)),
+        ("Either", 2) => Arc::new(Either(pattern(0)?, pattern(1)?)),
+        ("Except", 2) => Arc::new(Except(pattern(0)?, pattern(1)?)),
+        ("Color", _) | ("Tier", _) | ("Stack", _) | ("AdjacentT2", _) | ("Diagonal", _) | ("Line", _)
+        | ("Corner", _) | ("Square", _) | ("AdjacentColors", _) | ("DiagonalStacks", _) | ("Surround", _)
+        | ("Both", _) | ("Either", _) | ("Except", _) => {
+            return Err(Error::notation(list, offset));
+        },
+        _ => return Err(Error::notation(name, 0)),
+    };
+    Ok(pattern)
+}
+
+/// Arguments of a pattern, each with its byte offset in the whole notation.
+///
+/// Arguments are separated by commas outside parentheses, so patterns given
+/// as arguments keep their own arguments.
+fn arguments(list: &str, offset: usize) -> Vec<(usize, &str)> {
+    let mut arguments = Vec::new();
+    let (mut start, mut depth) = (0, 0);
+    for (index, letter) in list.char_indices() {
+        match letter {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push((offset + start, &list[start..index]));
+                start = index + 1;
+            },
+            _ => {},
         }
-        None
     }
+    arguments.push((offset + start, &list[start..]));
+    arguments
+}
+
+/// Names of the patterns read by `parse_pattern`.
+const PATTERN_NAMES: [&str; 15] = [
+    "Color", "Tier", "Stack", "AdjacentColors", "AdjacentT2", "DiagonalStacks", "Surround", "Diagonal", "Line",
+    "Corner", "Square", "Shape", "Both", "Either", "Except",
+];
+
+/// Pattern notation as `parse_pattern` reads it. In lenient mode, the names
+/// of patterns may be written in any case and their arguments are normalized
+/// as by `notation::normalized`, keeping every offset.
+pub(crate) fn canonical_pattern(notation: &str, mode: Mode) -> String {
+    if mode == Mode::Strict {
+        return notation.to_string();
+    }
+    let mut canonical = String::with_capacity(notation.len());
+    let mut rest = notation;
+    while let Some(index) = rest.find('(') {
+        let start = rest[..index].rfind(|letter: char| !letter.is_ascii_alphanumeric()).map_or(0, |start| start + 1);
+        let name = &rest[start..index];
+        canonical.push_str(&normalized(&rest[..start], mode));
+        canonical.push_str(PATTERN_NAMES.iter().find(|known| known.eq_ignore_ascii_case(name)).copied().unwrap_or(name));
+        canonical.push('(');
+        rest = &rest[index + 1..];
+    }
+    canonical.push_str(&normalized(rest, mode));
+    canonical
+}
+
+/// Parse the cells of a shape, each given as a condition and a position,
+/// e.g. `r2+@j3`, followed by `fixed` without rotations and `mirror` with
+/// reflections. A shape has at least one cell.
+fn parse_shape(arguments: &[(usize, &str)], list: &str, offset: usize) -> Result<ShapePattern, Error> {
+    let flags = arguments.iter().rev().take_while(|(_, flag)| *flag == "fixed" || *flag == "mirror").count();
+    let (cells, flags) = arguments.split_at(arguments.len() - flags);
+    if cells.is_empty() {
+        return Err(Error::notation(list, offset));
+    }
+    let cells = cells
+        .iter()
+        .map(|(offset, cell)| {
+            let (predicate, position) = cell.split_once('@').ok_or_else(|| Error::notation(cell, *offset))?;
+            let predicate = parse_predicate(predicate).map_err(|error| error.shifted(*offset))?;
+            let position = Position::try_from(position).map_err(|error| error.shifted(offset + cell.len() - position.len()))?;
+            Ok((position, predicate))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut shape = ShapePattern::new(cells);
+    if flags.iter().any(|(_, flag)| *flag == "fixed") {
+        shape = shape.fixed();
+    }
+    if flags.iter().any(|(_, flag)| *flag == "mirror") {
+        shape = shape.reflected();
+    }
+    Ok(shape)
 }
 
 #[cfg(test)]
@@ -369,38 +677,69 @@ mod tests {
     use crate::error::Error;
     use std::convert::TryFrom;
 
+    /// Pattern implementing only the required parts of the trait.
+    struct Centre;
+
+    impl Pattern for Centre {
+        fn fit_at(&self, position: &Position, board: &Board) -> Option<Mask> {
+            match (position, board.get(position)) {
+                (Position::j2, Some(_)) => Some(btreeset!{*position}),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn custom_pattern() -> Result<(), Error> {
+        assert!(Centre.to_notation().ends_with("Centre"));
+        assert!(parse_pattern(&Centre.to_notation()).is_err());
+        let board = Board::try_from("b1j2 r1j3")?;
+        assert_eq!(Centre.fit_bits(&board), vec![Position::j2.bit()]);
+        assert_eq!(Centre.count(&board), 1);
+        Ok(())
+    }
+
+    /// Fits of a pattern on a board, checking that `fit_bits` finds the same.
+    fn fits(pattern: &dyn Pattern, board: &Board) -> MaskSet {
+        let fits = pattern.fit(board);
+        let mut bits: Vec<u16> = fits.iter().map(mask_bits).collect();
+        bits.sort_unstable();
+        assert_eq!(pattern.fit_bits(board), bits, "{} on {}", pattern.to_notation(), board);
+        fits
+    }
+
     #[test]
     fn color_fit() -> Result<(), Error> {
         let board = Board::try_from("r3i2 r1i3 g2j4 y1j1 r4k1")?;
-        assert_eq!(Color::Red.fit(&board), btreeset!{
+        assert_eq!(fits(&Color::Red, &board), btreeset!{
             btreeset!{Position::i2},
             btreeset!{Position::i3},
             btreeset!{Position::k1},
         });
-        assert_eq!(Color::Green.fit(&board), btreeset!{
+        assert_eq!(fits(&Color::Green, &board), btreeset!{
             btreeset!{Position::j4},
         });
-        assert_eq!(Color::Yellow.fit(&board), btreeset!{
+        assert_eq!(fits(&Color::Yellow, &board), btreeset!{
             btreeset!{Position::j1},
         });
-        assert_eq!(Color::Blue.fit(&board).len(), 0);
+        assert_eq!(fits(&Color::Blue, &board).len(), 0);
         Ok(())
     }
 
     #[test]
     fn tier_fit() -> Result<(), Error> {
         let board = Board::try_from("r3i2 r1i3 g2j4 y1j1 r4k1")?;
-        assert_eq!(Tier::First.fit(&board), btreeset!{
+        assert_eq!(fits(&Tier::First, &board), btreeset!{
             btreeset!{Position::i3},
             btreeset!{Position::j1},
         });
-        assert_eq!(Tier::Second.fit(&board), btreeset!{
+        assert_eq!(fits(&Tier::Second, &board), btreeset!{
             btreeset!{Position::j4},
         });
-        assert_eq!(Tier::Third.fit(&board), btreeset!{
+        assert_eq!(fits(&Tier::Third, &board), btreeset!{
             btreeset!{Position::i2},
         });
-        assert_eq!(Tier::Fourth.fit(&board), btreeset!{
+        assert_eq!(fits(&Tier::Fourth, &board), btreeset!{
             btreeset!{Position::k1},
         });
         Ok(())
@@ -409,19 +748,19 @@ mod tests {
     #[test]
     fn stack_fit() -> Result<(), Error> {
         let board = Board::try_from("g2j2 g2k3 y3i1 r1i2 g2k4 y3k2")?;
-        assert_eq!(Stack::try_from("g2")?.fit(&board), btreeset!{
+        assert_eq!(fits(&Stack::try_from("g2")?, &board), btreeset!{
             btreeset!{Position::j2},
             btreeset!{Position::k3},
             btreeset!{Position::k4},
         });
-        assert_eq!(Stack::try_from("y3")?.fit(&board), btreeset!{
+        assert_eq!(fits(&Stack::try_from("y3")?, &board), btreeset!{
             btreeset!{Position::i1},
             btreeset!{Position::k2},
         });
-        assert_eq!(Stack::try_from("r1")?.fit(&board), btreeset!{
+        assert_eq!(fits(&Stack::try_from("r1")?, &board), btreeset!{
             btreeset!{Position::i2},
         });
-        assert_eq!(Stack::try_from("b2")?.fit(&board).len(), 0);
+        assert_eq!(fits(&Stack::try_from("b2")?, &board).len(), 0);
         Ok(())
     }
 
@@ -433,7 +772,7 @@ mod tests {
         // g1 b3 y2
         let board = Board::try_from("g1i1 b2i2 r2i3 b3j1 g4j2 b3j3 g1j4 y2k1 b3k2 y1k4 r2l2 y2l4")?;
         let gb = AdjacentColors(Color::Green, Color::Blue);
-        assert_eq!(gb.fit(&board), btreeset!{
+        assert_eq!(fits(&gb, &board), btreeset!{
             btreeset!{Position::i1, Position::j1},
             btreeset!{Position::i1, Position::i2},
             btreeset!{Position::i2, Position::j2},
@@ -453,16 +792,16 @@ mod tests {
         //    b3 r2 b2
         let board = Board::try_from("r2i2 r2i3 b3j1 r4j2 r3j3 g1j4 r2k1 b3k2 r1k4 b2l1 b1l3")?;
         let rdiag = Diagonal(Color::Red);
-        assert_eq!(rdiag.fit(&board), btreeset!{
+        assert_eq!(fits(&rdiag, &board), btreeset!{
             btreeset!{Position::i2, Position::j3, Position::k4},
             btreeset!{Position::i3, Position::j2, Position::k1},
         });
         let bdiag = Diagonal(Color::Blue);
-        assert_eq!(bdiag.fit(&board), btreeset!{
+        assert_eq!(fits(&bdiag, &board), btreeset!{
             btreeset!{Position::j1, Position::k2, Position::l3},
         });
         let ydiag = Diagonal(Color::Yellow);
-        assert_eq!(ydiag.fit(&board).len(), 0);
+        assert_eq!(fits(&ydiag, &board).len(), 0);
         Ok(())
     }
 
@@ -473,15 +812,15 @@ mod tests {
         // r2 r4 r3
         //    b3 r2 b2
         let board = Board::try_from("r2i2 r2i3 b1i4 b3j1 r4j2 r3j3 g1j4 r2k1 r3k2 r1k3 r1k4 b2l1")?;
-        assert_eq!(Line(Color::Red).fit(&board), btreeset!{
+        assert_eq!(fits(&Line(Color::Red), &board), btreeset!{
             btreeset!{Position::i2, Position::j2, Position::k2},
             btreeset!{Position::i3, Position::j3, Position::k3},
             btreeset!{Position::k1, Position::k2, Position::k3},
             btreeset!{Position::k2, Position::k3, Position::k4},
         });
-        assert_eq!(Line(Color::Green).fit(&board).len(), 0);
-        assert_eq!(Line(Color::Blue).fit(&board).len(), 0);
-        assert_eq!(Line(Color::Yellow).fit(&board).len(), 0);
+        assert_eq!(fits(&Line(Color::Green), &board).len(), 0);
+        assert_eq!(fits(&Line(Color::Blue), &board).len(), 0);
+        assert_eq!(fits(&Line(Color::Yellow), &board).len(), 0);
         Ok(())
     }
 
@@ -492,7 +831,7 @@ mod tests {
         // r2 r4 b3
         //    b3 b2 b2
         let board = Board::try_from("r2i2 r2i3 r1i4 b3j1 r4j2 r3j3 g1j4 b2k1 b3k2 r1k3 r1k4 b2l1")?;
-        assert_eq!(Corner(Color::Red).fit(&board), btreeset!{
+        assert_eq!(fits(&Corner(Color::Red), &board), btreeset!{
             btreeset!{Position::i2, Position::i3, Position::j2},
             btreeset!{Position::i3, Position::i4, Position::j3},
             btreeset!{Position::i3, Position::i2, Position::j3},
@@ -501,12 +840,12 @@ mod tests {
             btreeset!{Position::j3, Position::k3, Position::j2},
             btreeset!{Position::k3, Position::j3, Position::k4},
         });
-        assert_eq!(Corner(Color::Blue).fit(&board), btreeset!{
+        assert_eq!(fits(&Corner(Color::Blue), &board), btreeset!{
             btreeset!{Position::j1, Position::k1, Position::k2},
             btreeset!{Position::k1, Position::k2, Position::l1},
         });
-        assert_eq!(Corner(Color::Green).fit(&board).len(), 0);
-        assert_eq!(Corner(Color::Yellow).fit(&board).len(), 0);
+        assert_eq!(fits(&Corner(Color::Green), &board).len(), 0);
+        assert_eq!(fits(&Corner(Color::Yellow), &board).len(), 0);
         Ok(())
     }
 
@@ -517,15 +856,15 @@ mod tests {
         // r2 b4 b3
         //    b3 b2 b2
         let board = Board::try_from("r2i2 r2i3 r1i4 b3j1 b4j2 r3j3 r1j4 b2k1 b3k2 r1k3 r1k4 b2l1 y4l3 r3l4")?;
-        assert_eq!(Square(Color::Red).fit(&board), btreeset!{
+        assert_eq!(fits(&Square(Color::Red), &board), btreeset!{
             btreeset!{Position::i3, Position::i4, Position::j3, Position::j4},
             btreeset!{Position::j3, Position::j4, Position::k3, Position::k4},
         });
-        assert_eq!(Square(Color::Blue).fit(&board), btreeset!{
+        assert_eq!(fits(&Square(Color::Blue), &board), btreeset!{
             btreeset!{Position::j1, Position::j2, Position::k1, Position::k2},
         });
-        assert_eq!(Square(Color::Green).fit(&board).len(), 0);
-        assert_eq!(Square(Color::Yellow).fit(&board).len(), 0);
+        assert_eq!(fits(&Square(Color::Green), &board).len(), 0);
+        assert_eq!(fits(&Square(Color::Yellow), &board).len(), 0);
         Ok(())
     }
 
@@ -536,16 +875,16 @@ mod tests {
         // r2 b4 b3
         //    b3 b2 b2
         let board = Board::try_from("r2i2 r2i3 r1i4 b3j1 b4j2 r2j3 r2j4 b2k1 b3k2 r1k3 r1k4 b2l1 y4l3 r3l4")?;
-        assert_eq!(AdjacentT2(Color::Red).fit(&board), btreeset!{
+        assert_eq!(fits(&AdjacentT2(Color::Red), &board), btreeset!{
             btreeset!{Position::i2, Position::i3},
             btreeset!{Position::i3, Position::j3},
             btreeset!{Position::j3, Position::j4},
         });
-        assert_eq!(AdjacentT2(Color::Blue).fit(&board), btreeset!{
+        assert_eq!(fits(&AdjacentT2(Color::Blue), &board), btreeset!{
             btreeset!{Position::l1, Position::k1},
         });
-        assert_eq!(AdjacentT2(Color::Green).fit(&board).len(), 0);
-        assert_eq!(AdjacentT2(Color::Yellow).fit(&board).len(), 0);
+        assert_eq!(fits(&AdjacentT2(Color::Green), &board).len(), 0);
+        assert_eq!(fits(&AdjacentT2(Color::Yellow), &board).len(), 0);
         Ok(())
     }
 
@@ -562,16 +901,16 @@ mod tests {
             btreeset!{Position::i4, Position::j3},
             btreeset!{Position::j1, Position::k2},
         };
-        assert_eq!(DiagonalStacks(Color::Red, Color::Green).fit(&board), red_green_set);
-        assert_eq!(DiagonalStacks(Color::Green, Color::Red).fit(&board), red_green_set);
-        assert_eq!(DiagonalStacks(Color::Blue, Color::Green).fit(&board), btreeset!{
+        assert_eq!(fits(&DiagonalStacks(Color::Red, Color::Green), &board), red_green_set);
+        assert_eq!(fits(&DiagonalStacks(Color::Green, Color::Red), &board), red_green_set);
+        assert_eq!(fits(&DiagonalStacks(Color::Blue, Color::Green), &board), btreeset!{
             btreeset!{Position::i3, Position::j2},
             btreeset!{Position::k2, Position::l1},
         });
-        assert_eq!(DiagonalStacks(Color::Yellow, Color::Green).fit(&board), btreeset!{
+        assert_eq!(fits(&DiagonalStacks(Color::Yellow, Color::Green), &board), btreeset!{
             btreeset!{Position::k2, Position::l3},
         });
-        assert_eq!(DiagonalStacks(Color::Yellow, Color::Red).fit(&board).len(), 0);
+        assert_eq!(fits(&DiagonalStacks(Color::Yellow, Color::Red), &board).len(), 0);
         Ok(())
     }
 
@@ -582,16 +921,166 @@ mod tests {
         // g2 b4 g3
         // y4 r3 b2 y3
         let board = Board::try_from("y4i1 g2i2 g2i3 r2i4 r3j1 b4j2 g2j3 r2j4 b2k1 g3k2 r1k3 r1k4 y3l1 y4l3 r3l4")?;
-        assert_eq!(Surround(Color::Red, Color::Green).fit(&board), btreeset!{
+        assert_eq!(fits(&Surround(Color::Red, Color::Green), &board), btreeset!{
             btreeset!{Position::i2, Position::k2},
         });
-        assert_eq!(Surround(Color::Green, Color::Red).fit(&board), btreeset!{
+        assert_eq!(fits(&Surround(Color::Green, Color::Red), &board), btreeset!{
             btreeset!{Position::j1, Position::k3},
         });
-        assert_eq!(Surround(Color::Yellow, Color::Red).fit(&board), btreeset!{
+        assert_eq!(fits(&Surround(Color::Yellow, Color::Red), &board), btreeset!{
             btreeset!{Position::j1},
             btreeset!{Position::k3, Position::k4, Position::l4},
         });
         Ok(())
     }
+
+    #[test]
+    fn fit_bits() -> Result<(), Error> {
+        let mut notations: Vec<String> = crate::placeholder_deck().iter().map(|card| card.pattern().to_notation()).collect();
+        let shapes = [
+            "Tier(3)", "Shape(r2+@i1,?@j1,?@j2,mirror)", "Shape(g@i1,2@j1,g@k1,fixed)",
+            "Shape(1@i1,2@j1,3@k1)", "Shape([rg]&2-@i1,!b@j1,_@k1,*@k2)", "Shape(r|(g&!3+)@i1,[by]@j2,mirror)",
+        ];
+        notations.extend(shapes.iter().map(|notation| notation.to_string()));
+        for seed in 0..100 {
+            let board = crate::board::tests::random_board(seed)?;
+            for notation in notations.iter() {
+                fits(parse_pattern(notation)?.as_ref(), &board);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn predicate_shapes() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4 r1
+            3 y1 g3
+            2 b2 g2 y4
+            1 r1 b1 r3 g2
+        ")?;
+        let staircase = parse_pattern("Shape(1@i1,2@j1,3@k1,mirror)")?;
+        assert_eq!(fits(staircase.as_ref(), &board), btreeset!{btreeset!{Position::j1, Position::j2, Position::j3}});
+        let rising = parse_pattern("Shape(2-@i1,2+&!4@j1,3+@k1,fixed)")?;
+        assert_eq!(fits(rising.as_ref(), &board), btreeset!{btreeset!{Position::i2, Position::j2, Position::k2}});
+        let mixed = parse_pattern("Shape([rg]@i1,!r@j1,fixed)")?;
+        assert_eq!(fits(mixed.as_ref(), &board), btreeset!{
+            btreeset!{Position::i1, Position::j1},
+            btreeset!{Position::k1, Position::l1},
+            btreeset!{Position::j2, Position::k2},
+            btreeset!{Position::j3, Position::k3},
+            btreeset!{Position::i4, Position::j4},
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn completions() -> Result<(), Error> {
+        let board = Board::try_from("r1i1 r1j1")?;
+        let completions = Line(Color::Red).completions(&board, [Color::Red, Color::Green]);
+        assert_eq!(completions.len(), 14);
+        assert!(completions.iter().all(|completion| completion.gain == 1));
+        let stacked = [
+            (Position::k1, Stack::try_from("g1")?),
+            (Position::k1, Stack::try_from("r2")?),
+        ];
+        assert!(completions.iter().any(|completion| completion.placements == stacked));
+        let board = Board::try_from("r1i1 r1j1 r1i2 r1j2")?;
+        let completions = Line(Color::Red).completions(&board, [Color::Red, Color::Red]);
+        assert_eq!(completions[0].gain, 2);
+        assert_eq!(completions.iter().filter(|completion| completion.gain == 2).count(), 2);
+        assert!(Line(Color::Red).completions(&board, [Color::Blue, Color::Green]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn completions_probe() -> Result<(), Error> {
+        let board = Board::try_from("gr2i1 g2j1 grr3k1 b1j2 r1j3")?;
+        let patterns: Vec<Arc<dyn Pattern + Send + Sync>> = vec![
+            Arc::new(DiagonalStacks(Color::Red, Color::Green)),
+            Arc::new(Line(Color::Red)),
+            Arc::new(Surround(Color::Red, Color::Green)),
+        ];
+        for pattern in patterns.iter() {
+            let before = pattern.count(&board);
+            let completions = pattern.completions(&board, [Color::Red, Color::Green]);
+            assert!(!completions.is_empty(), "{}", pattern.to_notation());
+            for completion in completions {
+                let mut after = board.clone();
+                for (position, stack) in completion.placements.iter() {
+                    after.place(*position, *stack)?;
+                }
+                assert_eq!(pattern.count(&after), before + completion.gain as usize, "{}", pattern.to_notation());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn builtin_shapes() {
+        let shape = Builtin::DiagonalStacks.shape(Color::Red, Color::Green);
+        assert!(std::ptr::eq(shape, Builtin::DiagonalStacks.shape(Color::Red, Color::Green)));
+        assert_eq!(shape.to_notation(), DiagonalStacks(Color::Red, Color::Green).shape().to_notation());
+        assert_eq!(Builtin::AdjacentColors.shape(Color::Blue, Color::Yellow).to_notation(), "Shape(b@i1,y@j1)");
+        assert_eq!(Builtin::Line.placements(), &Line(Color::Blue).shape().placement_masks()[..]);
+    }
+
+    #[test]
+    fn pattern_combinators() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4
+            3 r1
+            2 r1
+            1 r2 r2 r2
+        ")?;
+        let tall = parse_pattern("Both(Line(r),Shape(2+@i1,2+@j1,2+@k1))")?;
+        assert_eq!(fits(tall.as_ref(), &board), btreeset!{btreeset!{Position::i1, Position::j1, Position::k1}});
+        let either = parse_pattern("Either(Line(r),Diagonal(r))")?;
+        assert_eq!(fits(either.as_ref(), &board), Line(Color::Red).fit(&board).union(&Diagonal(Color::Red).fit(&board)).cloned().collect());
+        assert_eq!(either.fit(&board).len(), 2);
+        let low = parse_pattern("Except(Line(r),Shape(2+@i1,2+@j1,2+@k1))")?;
+        assert_eq!(fits(low.as_ref(), &board), btreeset!{btreeset!{Position::i1, Position::i2, Position::i3}});
+        assert_eq!(either.count(&board), 1);
+        let nested = "Either(Both(AdjacentColors(r,g),Tier(2)),Except(Surround(y,b),Shape(b@i1,fixed)))";
+        assert_eq!(parse_pattern(nested)?.to_notation(), nested);
+        assert_eq!(canonical_pattern("either(LINE(V),shape(R2+@I1,mirror))", Mode::Lenient), "Either(Line(b),Shape(r2+@i1,mirror))");
+        Ok(())
+    }
+
+    #[test]
+    fn pattern_notation() -> Result<(), Error> {
+        let notations = [
+            "Color(r)", "Tier(2)", "Stack(b4)", "AdjacentColors(r,g)", "AdjacentT2(y)",
+            "DiagonalStacks(g,b)", "Surround(y,b)", "Diagonal(b)", "Line(r)", "Corner(g)", "Square(y)",
+            "Shape(r@i1,r2@j1,3@j2)", "Shape(g@i1,g@i2,g@i3,g@j1,mirror)",
+            "Shape(r2+@i1,?@j1,2+@k1,fixed,mirror)",
+        ];
+        for notation in notations.iter() {
+            assert_eq!(parse_pattern(notation)?.to_notation(), *notation);
+        }
+        let board = Board::try_from("r1i1 r1j1 r1k1 r1l1")?;
+        assert_eq!(parse_pattern("Line(r)")?.fit(&board), Line(Color::Red).fit(&board));
+        let errors = [
+            ("Line(x)", "x", 5),
+            ("Surround(y,x)", "x", 11),
+            ("Stack(r5)", "5", 7),
+            ("Line(r,g)", "r,g", 5),
+            ("Surround(y)", "y", 9),
+            ("Line(r", "r", 5),
+            ("Ring(r)", "Ring", 0),
+            ("Line", "Line", 0),
+            ("Shape(r@i1,ri2)", "ri2", 11),
+            ("Shape(r@i1,x@i2)", "x", 11),
+            ("Shape(r@i1,r@z2)", "z2", 13),
+            ("Shape(mirror)", "mirror", 6),
+            ("Shape(fixed,mirror)", "fixed,mirror", 6),
+            ("Both(Line(r))", "Line(r)", 5),
+            ("Either(Line(r),Ring(g))", "Ring", 15),
+            ("Except(Line(r),Line(x))", "x", 20),
+        ];
+        for (notation, token, offset) in errors.iter() {
+            assert!(matches!(parse_pattern(notation).err(), Some(Error::InvalidNotation { token: found, offset: at }) if found == *token && at == *offset), "{}", notation);
+        }
+        Ok(())
+    }
 }
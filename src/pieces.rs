@@ -1,7 +1,9 @@
 use std::convert::TryFrom;
+use std::fmt;
+use strum::EnumIter;
 use crate::Error;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Color {
     Red,
     Green,
@@ -14,19 +16,36 @@ impl TryFrom<&str> for Color {
 
     fn try_from(notation: &str) -> Result<Self, Self::Error> {
         if notation.len() != 1 {
-            Err(Error::InvalidNotation)
+            Err(Error::notation(notation, 0))
         } else {
             match notation.chars().next() {
                 Some('r') => Ok(Color::Red),
                 Some('g') => Ok(Color::Green),
                 Some('b') => Ok(Color::Blue),
                 Some('y') => Ok(Color::Yellow),
-                _ => Err(Error::InvalidNotation),
+                _ => Err(Error::notation(notation, 0)),
             }
         }
     }
 }
 
+impl Color {
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Red => write!(f, "r"),
+            Color::Green => write!(f, "g"),
+            Color::Blue => write!(f, "b"),
+            Color::Yellow => write!(f, "y"),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Tier {
     First,
@@ -40,19 +59,41 @@ impl TryFrom<&str> for Tier {
 
     fn try_from(notation: &str) -> Result<Self, Self::Error> {
         if notation.len() != 1 {
-            Err(Error::InvalidNotation)
+            Err(Error::notation(notation, 0))
         } else {
             match notation.chars().next() {
                 Some('1') => Ok(Tier::First),
                 Some('2') => Ok(Tier::Second),
                 Some('3') => Ok(Tier::Third),
                 Some('4') => Ok(Tier::Fourth),
-                _ => Err(Error::InvalidNotation),
+                _ => Err(Error::notation(notation, 0)),
             }
         }
     }
 }
 
+impl Tier {
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+
+    /// Tier of a piece placed on top of a stack of this tier.
+    pub fn next(self) -> Option<Tier> {
+        match self {
+            Tier::First => Some(Tier::Second),
+            Tier::Second => Some(Tier::Third),
+            Tier::Third => Some(Tier::Fourth),
+            Tier::Fourth => None,
+        }
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.level())
+    }
+}
+
 pub trait TierLevel {
     fn level(&self) -> u8;
 
@@ -97,17 +138,28 @@ impl TryFrom<&str> for Stack {
     type Error = Error;
 
     fn try_from(notation: &str) -> Result<Self, Self::Error> {
-        if notation.len() != 2 {
-            Err(Error::InvalidNotation)
+        if notation.len() != 2 || !notation.is_char_boundary(1) {
+            Err(Error::notation(notation, 0))
         } else {
             Ok(Stack {
                 color: Color::try_from(&notation[0..1])?,
-                tier: Tier::try_from(&notation[1..2])?,
+                tier: Tier::try_from(&notation[1..2]).map_err(|error| error.shifted(1))?,
             })
         }
     }
 }
 
+impl Stack {
+    pub fn to_notation(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.color, self.tier)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -141,19 +193,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn pieces_to_notation() -> Result<(), Error> {
+        for notation in ["r", "g", "b", "y"].iter() {
+            assert_eq!(Color::try_from(*notation)?.to_notation(), *notation);
+        }
+        for notation in ["1", "2", "3", "4"].iter() {
+            assert_eq!(Tier::try_from(*notation)?.to_notation(), *notation);
+        }
+        assert_eq!(Stack::try_from("b3")?.to_notation(), "b3");
+        assert_eq!(format!("{}", Stack::try_from("y1")?), "y1");
+        Ok(())
+    }
+
     #[test]
     fn tier_level_is_on_top_of() {
         assert!(Tier::First.is_on_top_of(&None));
         assert!(Tier::Second.is_on_top_of(&Tier::First));
         assert!(Tier::Third.is_on_top_of(&Tier::Second));
         assert!(Tier::Fourth.is_on_top_of(&Tier::Third));
-        assert_eq!(Tier::First.is_on_top_of(&Tier::First), false);
-        assert_eq!(Tier::Third.is_on_top_of(&Tier::First), false);
+        assert!(!Tier::First.is_on_top_of(&Tier::First));
+        assert!(!Tier::Third.is_on_top_of(&Tier::First));
         assert!(Stack::try_from("r4")
             .unwrap()
             .is_on_top_of(&Stack::try_from("g3").unwrap()));
     }
 
+    #[test]
+    fn tier_next() {
+        assert_eq!(Tier::First.next(), Some(Tier::Second));
+        assert_eq!(Tier::Third.next(), Some(Tier::Fourth));
+        assert_eq!(Tier::Fourth.next(), None);
+    }
+
     #[test]
     fn stack_notation() -> Result<(), Error> {
         Stack::try_from("r3")?;
@@ -162,6 +234,8 @@ mod tests {
         assert!(Stack::try_from("e3").is_err());
         assert!(Stack::try_from("sdfsf").is_err());
         assert!(Stack::try_from("x").is_err());
+        assert!(Stack::try_from("é").is_err());
+        assert!(matches!(Stack::try_from("r0").unwrap_err(), Error::InvalidNotation { token, offset: 1 } if token == "0"));
         Ok(())
     }
 }
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use strum::IntoEnumIterator;
+use crate::{Board, Card, Color, Error, Position, Reason, Stack, Tier};
+
+/// Most pieces a player may keep in their reserve.
+pub const RESERVE_LIMIT: usize = 4;
+
+/// Most cards a player may hold in their hand.
+pub const HAND_LIMIT: usize = 4;
+
+/// Pieces every player starts with in the centre of their board.
+pub(crate) const STARTING_REEF: [(Position, Color); 4] = [
+    (Position::j3, Color::Red),
+    (Position::k3, Color::Green),
+    (Position::j2, Color::Blue),
+    (Position::k2, Color::Yellow),
+];
+
+/// Pieces a player has collected but not placed yet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reserve {
+    pieces: HashMap<Color, u8>,
+}
+
+impl Reserve {
+    pub fn new() -> Reserve {
+        Reserve {
+            pieces: HashMap::with_capacity(4),
+        }
+    }
+
+    /// Number of pieces of the given colour.
+    pub fn count(&self, color: Color) -> u8 {
+        self.pieces.get(&color).copied().unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.values().map(|count| *count as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= RESERVE_LIMIT
+    }
+
+    /// Number of pieces that have to be discarded to receive the given ones.
+    pub fn overflow(&self, pieces: &[Color]) -> usize {
+        (self.len() + pieces.len()).saturating_sub(RESERVE_LIMIT)
+    }
+
+    /// Check whether all the given pieces are in the reserve.
+    pub fn contains(&self, pieces: &[Color]) -> bool {
+        pieces.iter().all(|color| {
+            let needed = pieces.iter().filter(|piece| *piece == color).count();
+            self.count(*color) as usize >= needed
+        })
+    }
+
+    pub fn add(&mut self, color: Color) -> Result<(), Error> {
+        if self.is_full() {
+            return Err(Error::rule(Reason::ReserveFull));
+        }
+        *self.pieces.entry(color).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// Remove the given pieces, either all of them or none.
+    pub fn remove(&mut self, pieces: &[Color]) -> Result<(), Error> {
+        if !self.contains(pieces) {
+            return Err(Error::rule(Reason::MissingPieces));
+        }
+        for color in pieces.iter() {
+            let count = self.pieces.get_mut(color).expect("reserve holds the piece");
+            *count -= 1;
+            if *count == 0 {
+                self.pieces.remove(color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pieces listed colour by colour, e.g. `rrb`, or `-` for an empty reserve.
+impl fmt::Display for Reserve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "-");
+        }
+        for color in Color::iter() {
+            for _ in 0..self.count(color) {
+                write!(f, "{}", color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for Reserve {
+    type Error = Error;
+
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        let mut reserve = Reserve::new();
+        if notation == "-" {
+            return Ok(reserve);
+        }
+        if notation.is_empty() {
+            return Err(Error::notation(notation, 0));
+        }
+        for (offset, _) in notation.char_indices() {
+            let token = notation.get(offset..offset + 1).ok_or_else(|| Error::notation(&notation[offset..], offset))?;
+            let color = Color::try_from(token).map_err(|error| error.shifted(offset))?;
+            reserve.add(color).map_err(|_| Error::notation(token, offset))?;
+        }
+        Ok(reserve)
+    }
+}
+
+/// Cards a player holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hand {
+    cards: Vec<Card>,
+}
+
+impl Hand {
+    pub fn new() -> Hand {
+        Hand {
+            cards: Vec::with_capacity(HAND_LIMIT),
+        }
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.cards.len() >= HAND_LIMIT
+    }
+
+    /// Find a card in hand by its identifier.
+    pub fn get(&self, id: u8) -> Option<&Card> {
+        self.cards.iter().find(|card| card.id() == id)
+    }
+
+    pub fn add(&mut self, card: Card) -> Result<(), Error> {
+        if self.is_full() {
+            return Err(Error::rule(Reason::HandFull));
+        }
+        self.cards.push(card);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: u8) -> Result<Card, Error> {
+        let index = self.index(id).ok_or(Error::rule(Reason::CardNotInHand))?;
+        Ok(self.cards.remove(index))
+    }
+
+    /// Place of a card in hand, counting from the card taken first.
+    pub(crate) fn index(&self, id: u8) -> Option<usize> {
+        self.cards.iter().position(|card| card.id() == id)
+    }
+
+    /// Put a removed card back at its place in hand.
+    pub(crate) fn insert(&mut self, index: usize, card: Card) {
+        self.cards.insert(index, card);
+    }
+}
+
+/// Card references separated by commas, e.g. `c3,c17`, or `-` for an empty hand.
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "-");
+        }
+        let references: Vec<String> = self.cards.iter().map(|card| card.reference()).collect();
+        write!(f, "{}", references.join(","))
+    }
+}
+
+impl Hand {
+    /// Parse a hand of cards written as their references, e.g. `c3,c17`,
+    /// looking the cards up in the deck the game is played with.
+    ///
+    /// A card may be held only once, so a repeated reference is an error.
+    pub fn parse(notation: &str, deck: &[Card]) -> Result<Hand, Error> {
+        let mut hand = Hand::new();
+        if notation == "-" {
+            return Ok(hand);
+        }
+        let mut offset = 0;
+        for reference in notation.split(',') {
+            let id = Card::parse_reference(reference).map_err(|error| error.shifted(offset))?;
+            let card = deck.iter().find(|card| card.id() == id).ok_or_else(|| Error::notation(reference, offset))?;
+            if hand.get(id).is_some() {
+                return Err(Error::notation(reference, offset));
+            }
+            hand.add(card.clone()).map_err(|_| Error::notation(reference, offset))?;
+            offset += reference.len() + 1;
+        }
+        Ok(hand)
+    }
+}
+
+/// Everything a single player owns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    pub(crate) board: Board,
+    pub(crate) hand: Hand,
+    pub(crate) reserve: Reserve,
+    pub(crate) score: u32,
+}
+
+impl Player {
+    pub(crate) fn new() -> Player {
+        let mut board = Board::new();
+        for (position, color) in STARTING_REEF.iter() {
+            board.place(*position, Stack { color: *color, tier: Tier::First })
+                .expect("starting reef is placed on an empty board");
+        }
+        Player {
+            board,
+            hand: Hand::new(),
+            reserve: Reserve::new(),
+            score: 0,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn hand(&self) -> &Hand {
+        &self.hand
+    }
+
+    pub fn reserve(&self) -> &Reserve {
+        &self.reserve
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// Play a card from hand, placing its pieces from the reserve and scoring it.
+    ///
+    /// The placements have to use exactly the pieces named by the card. An
+    /// illegal play leaves the player untouched. Returns the card played
+    /// together with the stacks its placements covered, as `Board::place`
+    /// does, and the points it scored.
+    pub(crate) fn play(&mut self, id: u8, placements: [(Position, Stack); 2]) -> Result<Played, Error> {
+        let pieces = self.hand.get(id).ok_or(Error::rule(Reason::CardNotInHand))?.pieces();
+        let colors = [placements[0].1.color, placements[1].1.color];
+        if colors != pieces && colors != [pieces[1], pieces[0]] {
+            return Err(Error::rule(Reason::WrongPieces));
+        }
+        let mut reserve = self.reserve.clone();
+        reserve.remove(&pieces)?;
+        let mut board = self.board.clone();
+        let mut covered = [None; 2];
+        for ((position, stack), covered) in placements.iter().zip(covered.iter_mut()) {
+            *covered = board.place(*position, *stack).map_err(|error| error.because(Reason::IllegalPlacement))?;
+        }
+        let card = self.hand.remove(id)?;
+        let score = card.score(&board);
+        self.score += score;
+        self.board = board;
+        self.reserve = reserve;
+        Ok((card, covered, score))
+    }
+}
+
+/// Card played together with the stacks its placements covered and the
+/// points it scored.
+pub(crate) type Played = (Card, [Option<Stack>; 2], u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::placeholder_deck;
+
+    #[test]
+    fn reserve_limit() -> Result<(), Error> {
+        let mut reserve = Reserve::new();
+        assert!(reserve.is_empty());
+        assert_eq!(reserve.overflow(&[Color::Red, Color::Red]), 0);
+        reserve.add(Color::Red)?;
+        reserve.add(Color::Red)?;
+        reserve.add(Color::Blue)?;
+        assert_eq!(reserve.overflow(&[Color::Red, Color::Red]), 1);
+        reserve.add(Color::Green)?;
+        assert!(reserve.is_full());
+        assert!(reserve.add(Color::Green).is_err());
+        assert_eq!(reserve.len(), 4);
+        assert_eq!(reserve.count(Color::Red), 2);
+        assert_eq!(reserve.count(Color::Yellow), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn reserve_remove() -> Result<(), Error> {
+        let mut reserve = Reserve::new();
+        reserve.add(Color::Red)?;
+        reserve.add(Color::Blue)?;
+        assert!(reserve.contains(&[Color::Red, Color::Blue]));
+        assert!(!reserve.contains(&[Color::Red, Color::Red]));
+        assert!(reserve.remove(&[Color::Red, Color::Red]).is_err());
+        assert_eq!(reserve.len(), 2);
+        reserve.remove(&[Color::Blue, Color::Red])?;
+        assert!(reserve.is_empty());
+        assert_eq!(reserve, Reserve::new());
+        Ok(())
+    }
+
+    #[test]
+    fn reserve_notation() -> Result<(), Error> {
+        let reserve = Reserve::try_from("ybr")?;
+        assert_eq!(reserve.count(Color::Yellow), 1);
+        assert_eq!(reserve.to_string(), "rby");
+        assert_eq!(Reserve::try_from("-")?, Reserve::new());
+        assert_eq!(Reserve::new().to_string(), "-");
+        assert!(matches!(Reserve::try_from("rrxg").unwrap_err(), Error::InvalidNotation { token, offset: 2 } if token == "x"));
+        assert!(Reserve::try_from("rrrrr").is_err());
+        assert!(Reserve::try_from("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hand_notation() -> Result<(), Error> {
+        let deck = placeholder_deck();
+        let hand = Hand::parse("c3,c17", &deck)?;
+        assert_eq!(hand.cards()[1].id(), 17);
+        assert_eq!(hand.to_string(), "c3,c17");
+        assert_eq!(Hand::parse("-", &deck)?, Hand::new());
+        assert_eq!(Hand::new().to_string(), "-");
+        assert!(matches!(Hand::parse("c3,c99", &deck).unwrap_err(), Error::InvalidNotation { token, offset: 3 } if token == "c99"));
+        assert!(Hand::parse("c1,c2,c3,c4,c5", &deck).is_err());
+        assert!(matches!(Hand::parse("c17,c17", &deck).unwrap_err(), Error::InvalidNotation { token, offset: 4 } if token == "c17"));
+        assert!(matches!(Hand::parse("c3,c17,c3", &deck).unwrap_err(), Error::InvalidNotation { token, offset: 7 } if token == "c3"));
+        assert!(Hand::parse("", &deck).is_err());
+        let custom = vec![Card::try_from("c99 Line(r) 2 rg")?];
+        assert_eq!(Hand::parse("c99", &custom)?.cards(), custom.as_slice());
+        assert!(Hand::parse("c3", &custom).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hand_limit() -> Result<(), Error> {
+        let cards = placeholder_deck();
+        let mut hand = Hand::new();
+        for card in cards.iter().take(HAND_LIMIT) {
+            hand.add(card.clone())?;
+        }
+        assert!(hand.is_full());
+        assert!(hand.add(cards[HAND_LIMIT].clone()).is_err());
+        assert_eq!(hand.get(2), Some(&cards[1]));
+        assert_eq!(hand.remove(2)?, cards[1]);
+        assert!(hand.remove(2).is_err());
+        assert_eq!(hand.len(), HAND_LIMIT - 1);
+        Ok(())
+    }
+}
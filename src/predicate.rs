@@ -0,0 +1,463 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+use crate::{Board, Color, Error, Position, Stack, Tier};
+
+const TIERS: [Tier; 4] = [Tier::First, Tier::Second, Tier::Third, Tier::Fourth];
+
+/// Condition on the stack at one cell of a `ShapePattern`.
+///
+/// Conditions combine with `And`, `Or` and `Not`, while whole patterns
+/// combine with `Both`, `Either` and `Except`.
+pub trait Predicate {
+    /// Whether the stack at a position, if there is one, meets the condition.
+    fn test(&self, stack: Option<&Stack>) -> bool;
+
+    /// Notation of the condition, e.g. `r` for any red stack.
+    ///
+    /// See `parse_predicate` for the reverse.
+    fn to_notation(&self) -> String;
+
+    /// Positions meeting the condition as a 16-bit board mask, see
+    /// `Position::bit`.
+    fn mask(&self, board: &Board) -> u16 {
+        Position::iter()
+            .filter(|position| self.test(board.get(position)))
+            .fold(0, |bits, position| bits | position.bit())
+    }
+}
+
+impl Predicate for Color {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        stack.is_some_and(|stack| stack.color == *self)
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        board.color_mask(*self)
+    }
+
+    fn to_notation(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Predicate for Tier {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        stack.is_some_and(|stack| stack.tier == *self)
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        board.tier_mask(*self)
+    }
+
+    fn to_notation(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Predicate for Stack {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        stack == Some(self)
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        board.color_mask(self.color) & board.tier_mask(self.tier)
+    }
+
+    fn to_notation(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// How the tier of a stack compares to the tier of a `Cell`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    Exactly,
+    AtLeast,
+    AtMost,
+}
+
+impl Bound {
+    fn allows(self, tier: Tier, bound: Tier) -> bool {
+        match self {
+            Bound::Exactly => tier == bound,
+            Bound::AtLeast => tier >= bound,
+            Bound::AtMost => tier <= bound,
+        }
+    }
+
+    fn to_notation(self) -> &'static str {
+        match self {
+            Bound::Exactly => "",
+            Bound::AtLeast => "+",
+            Bound::AtMost => "-",
+        }
+    }
+}
+
+/// Condition on the colour and the tier of a stack, either of which may be
+/// left open, e.g. `r2+` for a red stack of at least two pieces, `3-` for a
+/// stack of at most three pieces or `?` for any stack.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub color: Option<Color>,
+    pub tier: Option<Tier>,
+    pub bound: Bound,
+}
+
+impl Predicate for Cell {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        stack.is_some_and(|stack| {
+            let color = self.color.is_none_or(|color| stack.color == color);
+            let tier = self.tier.is_none_or(|tier| self.bound.allows(stack.tier, tier));
+            color && tier
+        })
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        let colors = self.color.map_or_else(|| board.occupied(), |color| board.color_mask(color));
+        let tiers = match self.tier {
+            Some(tier) => TIERS
+                .iter()
+                .filter(|other| self.bound.allows(**other, tier))
+                .fold(0, |bits, other| bits | board.tier_mask(*other)),
+            None => u16::MAX,
+        };
+        colors & tiers
+    }
+
+    fn to_notation(&self) -> String {
+        let color = self.color.map_or_else(|| "?".to_string(), |color| color.to_string());
+        match self.tier {
+            Some(tier) if self.color.is_none() => format!("{}{}", tier, self.bound.to_notation()),
+            Some(tier) => format!("{}{}{}", color, tier, self.bound.to_notation()),
+            None => color,
+        }
+    }
+}
+
+/// Colour letter or `?` for any colour, then optionally a tier followed by
+/// `+` for that tier or higher or `-` for that tier or lower, e.g. `r`, `?`,
+/// `r2`, `2+`, `?3+` or `b2-`.
+impl TryFrom<&str> for Cell {
+    type Error = Error;
+
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        let (color, tier) = match notation.chars().next() {
+            Some('?') => (None, &notation[1..]),
+            Some(letter) if letter.is_alphabetic() => {
+                let letter = &notation[..letter.len_utf8()];
+                (Some(Color::try_from(letter)?), &notation[letter.len()..])
+            },
+            Some(_) => (None, notation),
+            None => return Err(Error::notation(notation, 0)),
+        };
+        let offset = notation.len() - tier.len();
+        let (tier, bound) = match (tier.strip_suffix('+'), tier.strip_suffix('-')) {
+            (Some(tier), _) => (tier, Bound::AtLeast),
+            (_, Some(tier)) => (tier, Bound::AtMost),
+            _ => (tier, Bound::Exactly),
+        };
+        if tier.is_empty() && bound != Bound::Exactly {
+            return Err(Error::notation(&notation[offset..], offset));
+        }
+        let tier = match tier {
+            "" => None,
+            tier => Some(Tier::try_from(tier).map_err(|error| error.shifted(offset))?),
+        };
+        Ok(Cell { color, tier, bound })
+    }
+}
+
+/// Stack with any of the colours on top, e.g. `[rg]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors(pub Vec<Color>);
+
+impl Predicate for Colors {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        stack.is_some_and(|stack| self.0.contains(&stack.color))
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        self.0.iter().fold(0, |bits, color| bits | board.color_mask(*color))
+    }
+
+    fn to_notation(&self) -> String {
+        let colors: String = self.0.iter().map(|color| color.to_string()).collect();
+        format!("[{}]", colors)
+    }
+}
+
+/// Position without a stack, `_`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Empty;
+
+impl Predicate for Empty {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        stack.is_none()
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        !board.occupied()
+    }
+
+    fn to_notation(&self) -> String {
+        "_".to_string()
+    }
+}
+
+/// Any position, with or without a stack, `*`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Anything;
+
+impl Predicate for Anything {
+    fn test(&self, _stack: Option<&Stack>) -> bool {
+        true
+    }
+
+    fn mask(&self, _board: &Board) -> u16 {
+        u16::MAX
+    }
+
+    fn to_notation(&self) -> String {
+        "*".to_string()
+    }
+}
+
+/// Both conditions at once, e.g. `[rg]&2+`.
+pub struct And(pub Arc<dyn Predicate + Send + Sync>, pub Arc<dyn Predicate + Send + Sync>);
+
+impl Predicate for And {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        self.0.test(stack) && self.1.test(stack)
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        self.0.mask(board) & self.1.mask(board)
+    }
+
+    fn to_notation(&self) -> String {
+        format!("{}&{}", operand(self.0.as_ref(), "|"), operand(self.1.as_ref(), "|"))
+    }
+}
+
+/// Either of the conditions, e.g. `r|_`.
+pub struct Or(pub Arc<dyn Predicate + Send + Sync>, pub Arc<dyn Predicate + Send + Sync>);
+
+impl Predicate for Or {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        self.0.test(stack) || self.1.test(stack)
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        self.0.mask(board) | self.1.mask(board)
+    }
+
+    fn to_notation(&self) -> String {
+        format!("{}|{}", self.0.to_notation(), self.1.to_notation())
+    }
+}
+
+/// Opposite of the condition, e.g. `!r` for an empty position or a stack
+/// that is not red.
+pub struct Not(pub Arc<dyn Predicate + Send + Sync>);
+
+impl Predicate for Not {
+    fn test(&self, stack: Option<&Stack>) -> bool {
+        !self.0.test(stack)
+    }
+
+    fn mask(&self, board: &Board) -> u16 {
+        !self.0.mask(board)
+    }
+
+    fn to_notation(&self) -> String {
+        format!("!{}", operand(self.0.as_ref(), "&|"))
+    }
+}
+
+/// Notation of an operand, in parentheses when it combines conditions with
+/// any of the operators binding more loosely than the one applied to it.
+fn operand(predicate: &dyn Predicate, looser: &str) -> String {
+    let notation = predicate.to_notation();
+    let mut depth = 0;
+    let combined = notation.chars().any(|letter| {
+        match letter {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {},
+        }
+        depth == 0 && looser.contains(letter)
+    });
+    if combined {
+        format!("({})", notation)
+    } else {
+        notation
+    }
+}
+
+/// Parse the notation of a cell condition.
+///
+/// A condition is a `Cell`, such as `r`, `2+` or `?`, a set of colours such
+/// as `[rg]`, `_` for an empty position or `*` for any position. Conditions
+/// combine with `!` for not, `&` for and and `|` for or, binding in this
+/// order, and parentheses, e.g. `[rg]&2+|_`. Conditions on just a colour, a
+/// tier or a whole stack give the colour, tier or stack itself.
+pub fn parse_predicate(notation: &str) -> Result<Arc<dyn Predicate + Send + Sync>, Error> {
+    let mut parser = Parser { notation, offset: 0 };
+    let predicate = parser.or()?;
+    if parser.offset < notation.len() {
+        return Err(Error::notation(&notation[parser.offset..], parser.offset));
+    }
+    Ok(predicate)
+}
+
+/// Recursive descent over the notation of a condition.
+struct Parser<'a> {
+    notation: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.notation[self.offset..]
+    }
+
+    fn eat(&mut self, letter: char) -> bool {
+        let eaten = self.rest().starts_with(letter);
+        if eaten {
+            self.offset += letter.len_utf8();
+        }
+        eaten
+    }
+
+    fn or(&mut self) -> Result<Arc<dyn Predicate + Send + Sync>, Error> {
+        let mut predicate = self.and()?;
+        while self.eat('|') {
+            predicate = Arc::new(Or(predicate, self.and()?));
+        }
+        Ok(predicate)
+    }
+
+    fn and(&mut self) -> Result<Arc<dyn Predicate + Send + Sync>, Error> {
+        let mut predicate = self.not()?;
+        while self.eat('&') {
+            predicate = Arc::new(And(predicate, self.not()?));
+        }
+        Ok(predicate)
+    }
+
+    fn not(&mut self) -> Result<Arc<dyn Predicate + Send + Sync>, Error> {
+        if self.eat('!') {
+            return Ok(Arc::new(Not(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Arc<dyn Predicate + Send + Sync>, Error> {
+        let start = self.offset;
+        if self.eat('(') {
+            let predicate = self.or()?;
+            if !self.eat(')') {
+                return Err(Error::notation(self.rest(), self.offset));
+            }
+            return Ok(predicate);
+        }
+        if self.eat('_') {
+            return Ok(Arc::new(Empty));
+        }
+        if self.eat('*') {
+            return Ok(Arc::new(Anything));
+        }
+        if self.eat('[') {
+            let colors = self.rest().split(']').next().unwrap_or("");
+            let mut set = Vec::new();
+            for (index, letter) in colors.char_indices() {
+                let color = Color::try_from(&colors[index..index + letter.len_utf8()])
+                    .map_err(|error| error.shifted(self.offset + index))?;
+                set.push(color);
+            }
+            self.offset += colors.len();
+            let closed = self.eat(']');
+            if set.is_empty() || !closed {
+                return Err(Error::notation(&self.notation[start..self.offset], start));
+            }
+            return Ok(Arc::new(Colors(set)));
+        }
+        let length = self.rest().find(|letter| "|&!()[]_*".contains(letter)).unwrap_or(self.rest().len());
+        let token = &self.rest()[..length];
+        let cell = Cell::try_from(token).map_err(|error| error.shifted(start))?;
+        self.offset += length;
+        Ok(match cell {
+            Cell { color: Some(color), tier: None, .. } => Arc::new(color),
+            Cell { color: None, tier: Some(tier), bound: Bound::Exactly } => Arc::new(tier),
+            Cell { color: Some(color), tier: Some(tier), bound: Bound::Exactly } => Arc::new(Stack { color, tier }),
+            cell => Arc::new(cell),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_notation() -> Result<(), Error> {
+        for notation in ["?", "r", "2", "r2", "2+", "r2+", "3-", "b2-"].iter() {
+            assert_eq!(parse_predicate(notation)?.to_notation(), *notation);
+        }
+        assert_eq!(Cell::try_from("?3+")?, Cell { color: None, tier: Some(Tier::Third), bound: Bound::AtLeast });
+        assert_eq!(Cell::try_from("?3+")?.to_notation(), "3+");
+        let errors = [("x2", "x", 0), ("r5", "5", 1), ("?+", "+", 1), ("r-", "-", 1), ("r2+-", "2+", 1), ("", "", 0)];
+        for (notation, token, offset) in errors.iter() {
+            assert!(matches!(Cell::try_from(*notation).unwrap_err(), Error::InvalidNotation { token: found, offset: at } if found == *token && at == *offset), "{}", notation);
+        }
+        let cell = Cell::try_from("g2+")?;
+        assert!(!cell.test(Some(&Stack::try_from("g1")?)));
+        assert!(cell.test(Some(&Stack::try_from("g2")?)));
+        assert!(cell.test(Some(&Stack::try_from("g4")?)));
+        assert!(!cell.test(Some(&Stack::try_from("r4")?)));
+        assert!(!cell.test(None));
+        assert!(Cell::try_from("?")?.test(Some(&Stack::try_from("y1")?)));
+        let cell = Cell::try_from("3-")?;
+        assert!(cell.test(Some(&Stack::try_from("y3")?)));
+        assert!(!cell.test(Some(&Stack::try_from("y4")?)));
+        Ok(())
+    }
+
+    #[test]
+    fn predicate_notation() -> Result<(), Error> {
+        let notations = ["[rg]", "_", "*", "!r", "r&2+", "r|_", "[by]&2-|_", "!(r|g)", "(r|g)&!3", "!!_"];
+        for notation in notations.iter() {
+            assert_eq!(parse_predicate(notation)?.to_notation(), *notation);
+        }
+        assert_eq!(parse_predicate("(r&2)|(g)")?.to_notation(), "r&2|g");
+        let errors = [("[rx]", "x", 2), ("[]", "[]", 0), ("[rg", "[rg", 0), ("(r|g", "", 4), ("r&", "", 2), ("r)", ")", 1)];
+        for (notation, token, offset) in errors.iter() {
+            assert!(matches!(parse_predicate(notation).err().unwrap(), Error::InvalidNotation { token: found, offset: at } if found == *token && at == *offset), "{}", notation);
+        }
+        let predicate = parse_predicate("[rg]&!2|_")?;
+        assert!(predicate.test(Some(&Stack::try_from("r1")?)));
+        assert!(!predicate.test(Some(&Stack::try_from("g2")?)));
+        assert!(!predicate.test(Some(&Stack::try_from("b3")?)));
+        assert!(predicate.test(None));
+        Ok(())
+    }
+
+    #[test]
+    fn predicate_mask() -> Result<(), Error> {
+        let notations = ["r", "3", "y2", "2+", "b3-", "?", "[gy]", "_", "*", "!b", "[rb]&2+|_", "!(1|4)"];
+        for seed in 0..50 {
+            let board = crate::board::tests::random_board(seed)?;
+            for notation in notations.iter() {
+                let predicate = parse_predicate(notation)?;
+                let bits = Position::iter()
+                    .filter(|position| predicate.test(board.get(position)))
+                    .fold(0, |bits, position| bits | position.bit());
+                assert_eq!(predicate.mask(&board), bits, "{} on {}", notation, board);
+            }
+        }
+        Ok(())
+    }
+}
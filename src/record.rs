@@ -0,0 +1,524 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, Lines, Write};
+use crate::game::discard_notation;
+use crate::notation::{self, tokenize, Diagnostic, Mode, Token};
+use crate::{Action, Color, Error, Game, Position, Reason, Stack};
+
+/// Rules of the base game, the only variant supported so far.
+const STANDARD: &str = "standard";
+
+/// Move of a game record.
+///
+/// Unlike an `Action`, a take names the card taken rather than its pile, so
+/// a move reads the same regardless of the order of the piles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Move {
+    /// Take the card from the top of a display pile, discarding the listed pieces.
+    Take(u8, Vec<Color>),
+    /// Play a card from hand by its identifier, placing its two pieces.
+    Play(u8, [(Position, Stack); 2]),
+    /// Discard a card from hand by its identifier.
+    Discard(u8),
+}
+
+impl Move {
+    /// Describe an action about to be applied to the given game.
+    pub fn new(action: &Action, game: &Game) -> Result<Move, Error> {
+        match action {
+            Action::Take(pile, discard) => {
+                let card = game.display().top(*pile).ok_or(Error::rule(Reason::PileEmpty))?;
+                Ok(Move::Take(card.id(), discard.clone()))
+            },
+            Action::Play(id, placements) => Ok(Move::Play(*id, *placements)),
+            Action::Discard(id) => Ok(Move::Discard(*id)),
+        }
+    }
+
+    /// Action performing the move in the given game.
+    pub fn action(&self, game: &Game) -> Result<Action, Error> {
+        match self {
+            Move::Take(id, discard) => {
+                let display = game.display();
+                let pile = (0..display.piles())
+                    .find(|pile| display.top(*pile).map(|card| card.id()) == Some(*id))
+                    .ok_or(Error::rule(Reason::CardNotInDisplay))?;
+                Ok(Action::Take(pile, discard.clone()))
+            },
+            Move::Play(id, placements) => Ok(Action::Play(*id, *placements)),
+            Move::Discard(id) => Ok(Action::Discard(*id)),
+        }
+    }
+}
+
+/// Card reference, followed by the discarded pieces of a take, e.g. `c45 -rb`,
+/// or by the two placements of a play, e.g. `c17 b2j3 g1k4`. A discarded
+/// card is preceded by `D`, e.g. `D c17`.
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Move::Take(id, discard) if discard.is_empty() => write!(f, "c{}", id),
+            Move::Take(id, discard) => write!(f, "c{} {}", id, discard_notation(discard)),
+            Move::Play(id, [(first, a), (second, b)]) => {
+                write!(f, "c{} {}{} {}{}", id, a, first, b, second)
+            },
+            Move::Discard(id) => write!(f, "D c{}", id),
+        }
+    }
+}
+
+impl TryFrom<&str> for Move {
+    type Error = Error;
+
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        Move::parse(notation, Mode::Strict).map_err(|diagnostics| diagnostics[0].to_error(notation))
+    }
+}
+
+impl Move {
+    /// Parse the notation of a move, see `Display`, reporting every problem
+    /// found instead of only the first one.
+    pub fn parse(notation: &str, mode: Mode) -> Result<Move, Vec<Diagnostic>> {
+        let (tokens, diagnostics) = tokenize(notation, mode);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        let discarded = |kind: &Token| match mode {
+            Mode::Strict => kind.text == "D",
+            Mode::Lenient => kind.text.eq_ignore_ascii_case("D"),
+        };
+        match &tokens[..] {
+            [kind, reference] if discarded(kind) => {
+                Ok(Move::Discard(notation::reference(reference, mode).map_err(|diagnostic| vec![diagnostic])?))
+            },
+            [reference] => Ok(Move::Take(notation::reference(reference, mode).map_err(|diagnostic| vec![diagnostic])?, Vec::new())),
+            [reference, discard] => match (notation::reference(reference, mode), notation::discard(discard, mode)) {
+                (Ok(id), Ok(discard)) => Ok(Move::Take(id, discard)),
+                (id, discard) => Err(id.err().into_iter().chain(discard.err().into_iter().flatten()).collect()),
+            },
+            [reference, first, second] => {
+                let (id, placements) = notation::play(reference, first, second, mode)?;
+                Ok(Move::Play(id, placements))
+            },
+            _ => {
+                let message = "expected a take such as c45 -rb, a play such as c17 b2j3 g1k4 or a discard such as D c17";
+                Err(vec![Diagnostic::new(0..notation.len(), message.to_string())])
+            },
+        }
+    }
+}
+
+/// Metadata of a recorded game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Headers {
+    /// Names of the players in turn order.
+    pub players: Vec<String>,
+    pub date: Option<String>,
+    /// Seed the deck was shuffled with, see `Game::new`.
+    pub seed: u64,
+    pub variant: String,
+    /// Final scores in turn order, once the game is over.
+    pub result: Option<Vec<u32>>,
+}
+
+impl Headers {
+    pub fn new(players: Vec<String>, seed: u64) -> Headers {
+        Headers {
+            players,
+            date: None,
+            seed,
+            variant: STANDARD.to_string(),
+            result: None,
+        }
+    }
+
+    /// Build the headers from the name and value pairs read from a record.
+    fn parse(fields: &[(usize, String, String)], end: usize) -> Result<Headers, Error> {
+        let mut players = None;
+        let mut headers = Headers::new(Vec::new(), 0);
+        let mut seed = None;
+        for (line, name, value) in fields.iter() {
+            let invalid = || Error::notation(value, name.len() + 3).at_line(*line);
+            match name.as_str() {
+                "Players" => {
+                    let names: Vec<String> = value.split(',').map(|name| name.trim().to_string()).collect();
+                    if !(2..=4).contains(&names.len()) || names.iter().any(|name| name.is_empty()) {
+                        return Err(invalid());
+                    }
+                    players = Some(names);
+                },
+                "Date" => headers.date = Some(value.clone()),
+                "Seed" => seed = Some(value.parse().map_err(|_| invalid())?),
+                "Variant" if value == STANDARD => headers.variant = value.clone(),
+                "Variant" => return Err(invalid()),
+                "Result" if value == "*" => headers.result = None,
+                "Result" => {
+                    let scores = value
+                        .split('-')
+                        .map(|score| score.parse())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|_| invalid())?;
+                    headers.result = Some(scores);
+                },
+                _ => {},
+            }
+        }
+        headers.players = players.ok_or_else(|| Error::notation("Players", 0).at_line(end))?;
+        headers.seed = seed.ok_or_else(|| Error::notation("Seed", 0).at_line(end))?;
+        if let Some(scores) = &headers.result {
+            if scores.len() != headers.players.len() {
+                let (line, _, value) = fields.iter().rev().find(|(_, name, _)| name == "Result").expect("result is read");
+                return Err(Error::notation(value, 9).at_line(*line));
+            }
+        }
+        Ok(headers)
+    }
+}
+
+/// Header lines of the form `[Name "value"]`.
+impl fmt::Display for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "[Players \"{}\"]", self.players.join(", "))?;
+        if let Some(date) = &self.date {
+            writeln!(f, "[Date \"{}\"]", date)?;
+        }
+        writeln!(f, "[Seed \"{}\"]", self.seed)?;
+        writeln!(f, "[Variant \"{}\"]", self.variant)?;
+        match &self.result {
+            Some(scores) => {
+                let scores: Vec<String> = scores.iter().map(|score| score.to_string()).collect();
+                writeln!(f, "[Result \"{}\"]", scores.join("-"))
+            },
+            None => writeln!(f, "[Result \"*\"]"),
+        }
+    }
+}
+
+/// Split a header line into its name and value.
+fn header(line: &str) -> Result<(String, String), Error> {
+    let (name, value) = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .and_then(|line| line.split_once(' '))
+        .ok_or_else(|| Error::notation(line, 0))?;
+    if name.is_empty() || !name.bytes().all(|letter| letter.is_ascii_alphabetic()) {
+        return Err(Error::notation(name, 1));
+    }
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .filter(|value| !value.contains('"'))
+        .ok_or_else(|| Error::notation(value, name.len() + 2))?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Parse a numbered line of the move list, e.g. `3. c17 b2j3 g1k4`.
+///
+/// In lenient mode, the number may be surrounded by any whitespace.
+fn numbered(line: &str, number: usize, mode: Mode) -> Result<Move, Error> {
+    let split = match mode {
+        Mode::Strict => line.split_once(". "),
+        Mode::Lenient => line.split_once('.'),
+    };
+    let (prefix, notation) = split.ok_or_else(|| Error::notation(line, 0))?;
+    let digits = match mode {
+        Mode::Strict => prefix,
+        Mode::Lenient => prefix.trim(),
+    };
+    if digits != number.to_string() {
+        return Err(Error::notation(digits, prefix.len() - prefix.trim_start().len()));
+    }
+    let offset = line.len() - notation.len();
+    Move::parse(notation, mode).map_err(|diagnostics| diagnostics[0].clone().shifted(offset).to_error(line))
+}
+
+/// Streaming reader of a game record.
+///
+/// The headers are read up front, the moves are then read one line at a
+/// time. Every error carries the line it was found on.
+pub struct RecordReader<R> {
+    lines: Lines<R>,
+    mode: Mode,
+    line: usize,
+    headers: Headers,
+    moves: usize,
+    pending: Option<String>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// Read the headers of a record, leaving its moves to be read one by one.
+    pub fn new(input: R) -> Result<RecordReader<R>, Error> {
+        RecordReader::with_mode(input, Mode::Strict)
+    }
+
+    /// Read the headers of a record whose moves are read in the given mode,
+    /// see `Move::parse`.
+    pub fn with_mode(input: R, mode: Mode) -> Result<RecordReader<R>, Error> {
+        let mut lines = input.lines();
+        let mut line = 0;
+        let mut fields = Vec::new();
+        let mut pending = None;
+        for text in &mut lines {
+            let text = text.map_err(|error| Error::from(error).at_line(line + 1))?;
+            line += 1;
+            if text.trim().is_empty() {
+                if fields.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            if !text.starts_with('[') {
+                pending = Some(text);
+                break;
+            }
+            let (name, value) = header(&text).map_err(|error| error.at_line(line))?;
+            fields.push((line, name, value));
+        }
+        let headers = Headers::parse(&fields, line)?;
+        Ok(RecordReader {
+            lines,
+            mode,
+            line,
+            headers,
+            moves: 0,
+            pending,
+        })
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Replay the moves through the rules, stopping at the first illegal one.
+    pub fn replay(mut self) -> Result<Game, Error> {
+        let mut game = Game::new(self.headers.players.len(), self.headers.seed)?;
+        for next in &mut self {
+            let (line, step) = next?;
+            step.action(&game)
+                .and_then(|action| game.apply(action))
+                .map_err(|error| error.at_line(line))?;
+        }
+        Ok(game)
+    }
+}
+
+/// Moves of the record together with their line numbers.
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<(usize, Move), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.pending.take() {
+                Some(text) => text,
+                None => match self.lines.next()? {
+                    Ok(text) => {
+                        self.line += 1;
+                        text
+                    },
+                    Err(error) => return Some(Err(Error::from(error).at_line(self.line + 1))),
+                },
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            self.moves += 1;
+            let line = self.line;
+            return Some(numbered(&text, self.moves, self.mode).map(|step| (line, step)).map_err(|error| error.at_line(line)));
+        }
+    }
+}
+
+/// Record of a game: its headers followed by a numbered list of moves.
+///
+/// ```text
+/// [Players "Alice, Bob"]
+/// [Seed "42"]
+/// [Variant "standard"]
+/// [Result "*"]
+///
+/// 1. c45
+/// 2. c46 -r
+/// 3. c45 y2j2 y1i1
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    headers: Headers,
+    moves: Vec<Move>,
+}
+
+impl Record {
+    /// Record the actions applied to a game set up with the seed from the headers.
+    ///
+    /// The result is filled in when the game is over.
+    pub fn new(mut headers: Headers, game: &Game) -> Record {
+        if game.is_over() {
+            headers.result = Some(game.players().iter().map(|player| player.score()).collect());
+        }
+        let moves = game.moves().collect();
+        Record { headers, moves }
+    }
+
+    /// Read a whole record without replaying it.
+    pub fn read<R: BufRead>(input: R) -> Result<Record, Error> {
+        Record::read_with_mode(input, Mode::Strict)
+    }
+
+    /// Read a whole record whose moves are read in the given mode, see
+    /// `Move::parse`.
+    pub fn read_with_mode<R: BufRead>(input: R, mode: Mode) -> Result<Record, Error> {
+        let mut reader = RecordReader::with_mode(input, mode)?;
+        let moves = (&mut reader)
+            .map(|next| next.map(|(_, step)| step))
+            .collect::<Result<Vec<Move>, Error>>()?;
+        Ok(Record {
+            headers: reader.headers,
+            moves,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut output: W) -> Result<(), Error> {
+        write!(output, "{}", self)?;
+        Ok(())
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.headers)?;
+        for (number, step) in self.moves.iter().enumerate() {
+            writeln!(f, "{}. {}", number + 1, step)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Board;
+
+    fn played(moves: usize) -> Result<Game, Error> {
+        let mut game = Game::new(3, 42)?;
+        for turn in 0..moves {
+            let actions = game.legal_actions();
+            game.apply(actions[turn % actions.len()].clone())?;
+        }
+        Ok(game)
+    }
+
+    #[test]
+    fn move_notation() -> Result<(), Error> {
+        let take = Move::try_from("c45 -rb")?;
+        assert_eq!(take, Move::Take(45, vec![Color::Red, Color::Blue]));
+        assert_eq!(take.to_string(), "c45 -rb");
+        assert_eq!(Move::try_from("c3")?.to_string(), "c3");
+        let play = Move::try_from("c17 b2j3 g1k4")?;
+        assert_eq!(play, Move::Play(17, [Board::interpret("b2j3")?, Board::interpret("g1k4")?]));
+        assert_eq!(play.to_string(), "c17 b2j3 g1k4");
+        assert_eq!(Move::try_from("D c17")?, Move::Discard(17));
+        assert_eq!(Move::Discard(17).to_string(), "D c17");
+        assert!(matches!(Move::try_from("D c").unwrap_err(), Error::InvalidNotation { token, offset: 2 } if token == "c"));
+        assert!(matches!(Move::try_from("c45 -rx").unwrap_err(), Error::InvalidNotation { token, offset: 6 } if token == "x"));
+        assert!(matches!(Move::try_from("c17 b2j3 g1z4").unwrap_err(), Error::InvalidNotation { token, offset: 11 } if token == "z4"));
+        assert!(matches!(Move::try_from("c45 rb").unwrap_err(), Error::InvalidNotation { token, offset: 4 } if token == "rb"));
+        assert!(Move::try_from("c17 b2j3 g1k4 y1i1").is_err());
+        assert!(Move::try_from("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn move_parse_lenient() -> Result<(), Error> {
+        assert_eq!(Move::parse(" C45, -RV ", Mode::Lenient), Ok(Move::try_from("c45 -rb")?));
+        assert_eq!(Move::parse("c17 V2J3,g1k4", Mode::Lenient), Ok(Move::try_from("c17 b2j3 g1k4")?));
+        assert_eq!(Move::parse("d c17", Mode::Lenient), Ok(Move::Discard(17)));
+        assert!(Move::parse("d c17", Mode::Strict).is_err());
+        let diagnostics = Move::parse("x45 -rq", Mode::Strict).unwrap_err();
+        let spans: Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.span.clone()).collect();
+        assert_eq!(spans, vec![0..3, 6..7]);
+        Ok(())
+    }
+
+    #[test]
+    fn record_round_trip() -> Result<(), Error> {
+        let game = played(30)?;
+        let mut headers = Headers::new(vec!["Alice".to_string(), "Bob".to_string(), "Carol".to_string()], 42);
+        headers.date = Some("2026-10-17".to_string());
+        let record = Record::new(headers, &game);
+        assert_eq!(record.moves().len(), 30);
+        let mut text = Vec::new();
+        record.write(&mut text)?;
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("[Players \"Alice, Bob, Carol\"]\n[Date \"2026-10-17\"]\n[Seed \"42\"]\n"));
+        assert_eq!(Record::read(text.as_bytes())?, record);
+        let reader = RecordReader::new(text.as_bytes())?;
+        assert_eq!(reader.headers(), record.headers());
+        assert_eq!(reader.replay()?, game);
+        Ok(())
+    }
+
+    #[test]
+    fn record_result() -> Result<(), Error> {
+        let mut game = played(0)?;
+        while !game.is_over() {
+            let actions = game.legal_actions();
+            game.apply(actions[0].clone())?;
+        }
+        let record = Record::new(Headers::new(vec!["a".into(), "b".into(), "c".into()], 42), &game);
+        let scores: Vec<u32> = game.players().iter().map(|player| player.score()).collect();
+        assert_eq!(record.headers().result, Some(scores));
+        let text = record.to_string();
+        assert_eq!(RecordReader::new(text.as_bytes())?.replay()?, game);
+        Ok(())
+    }
+
+    #[test]
+    fn record_illegal_move() -> Result<(), Error> {
+        let game = played(4)?;
+        let record = Record::new(Headers::new(vec!["a".into(), "b".into(), "c".into()], 42), &game);
+        let mut text = record.to_string();
+        text.push_str("5. c17 b2j3 g1k4\n6. c1\n");
+        let error = RecordReader::new(text.as_bytes())?.replay().unwrap_err();
+        assert!(matches!(error, Error::InvalidRecord { line: 10, source } if source.reason() == Some(Reason::CardNotInHand)));
+        let text = text.replace("6. c1", "7. c1");
+        let moves: Vec<Result<(usize, Move), Error>> = RecordReader::new(text.as_bytes())?.collect();
+        assert_eq!(moves.len(), 6);
+        assert!(matches!(moves[4], Ok((10, _))));
+        assert_eq!(moves[5].as_ref().unwrap_err().to_string(), Error::notation("7", 0).at_line(11).to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn record_lenient() -> Result<(), Error> {
+        let text = "[Players \"a, b\"]\n[Seed \"42\"]\n\n1.C45\n 2.  c46, -R\n";
+        let record = Record::read_with_mode(text.as_bytes(), Mode::Lenient)?;
+        assert_eq!(record.moves(), &[Move::Take(45, vec![]), Move::Take(46, vec![Color::Red])][..]);
+        assert!(Record::read(text.as_bytes()).is_err());
+        let error = Record::read_with_mode("[Players \"a, b\"]\n[Seed \"42\"]\n\n 1. c45 -x\n".as_bytes(), Mode::Lenient).unwrap_err();
+        assert_eq!(error.to_string(), Error::notation("x", 9).at_line(4).to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn record_headers() {
+        let errors = [
+            ("[Seed \"1\"]\n\n1. c1\n", Error::notation("Players", 0).at_line(2)),
+            ("[Players \"a\"]\n[Seed \"1\"]\n", Error::notation("a", 10).at_line(1)),
+            ("[Players \"a, b\"]\n[Seed \"x\"]\n", Error::notation("x", 7).at_line(2)),
+            ("[Players \"a, b\"]\n[Seed 1]\n", Error::notation("1", 6).at_line(2)),
+            ("[Players \"a, b\"]\n[Seed \"1\"]\n[Variant \"x\"]\n", Error::notation("x", 10).at_line(3)),
+            ("[Players \"a, b\"]\n[Seed \"1\"]\n[Result \"1-2-3\"]\n", Error::notation("1-2-3", 9).at_line(3)),
+            ("[Players \"a, b\"]\n[Seed \"1\"\n", Error::notation("[Seed \"1\"", 0).at_line(2)),
+        ];
+        for (text, error) in errors.iter() {
+            assert_eq!(RecordReader::new(text.as_bytes()).err().map(|error| error.to_string()), Some(error.to_string()), "{}", text);
+        }
+    }
+}
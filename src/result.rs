@@ -0,0 +1,123 @@
+use crate::Player;
+
+/// Final position of a single player.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Standing {
+    /// Index of the player in turn order.
+    pub player: usize,
+    pub score: u32,
+    /// Pieces left in the reserve, used to break ties on score.
+    pub reserve: usize,
+    /// Placing starting at 1, shared by players still tied after the tiebreak.
+    pub place: usize,
+}
+
+impl Standing {
+    fn beats(&self, other: &Standing) -> bool {
+        (self.score, self.reserve) > (other.score, other.reserve)
+    }
+}
+
+/// Final scores and ranking of a finished game.
+///
+/// Players are ranked by score. Ties are broken in favour of the player
+/// with more pieces left in their reserve, and players tied on both share
+/// the placing, e.g. `1, 1, 3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    standings: Vec<Standing>,
+}
+
+impl GameResult {
+    pub fn new(players: &[Player]) -> GameResult {
+        let mut standings: Vec<Standing> = players
+            .iter()
+            .enumerate()
+            .map(|(index, player)| Standing {
+                player: index,
+                score: player.score(),
+                reserve: player.reserve().len(),
+                place: 0,
+            })
+            .collect();
+        let places: Vec<usize> = standings
+            .iter()
+            .map(|standing| 1 + standings.iter().filter(|other| other.beats(standing)).count())
+            .collect();
+        for (standing, place) in standings.iter_mut().zip(places) {
+            standing.place = place;
+        }
+        standings.sort_by_key(|standing| (standing.place, standing.player));
+        GameResult { standings }
+    }
+
+    /// Standings ordered from the first place to the last.
+    pub fn standings(&self) -> &[Standing] {
+        &self.standings
+    }
+
+    /// Standing of the player with the given index.
+    pub fn standing(&self, player: usize) -> Option<&Standing> {
+        self.standings.iter().find(|standing| standing.player == player)
+    }
+
+    /// Indices of all the players sharing the first place.
+    pub fn winners(&self) -> Vec<usize> {
+        self.standings
+            .iter()
+            .filter(|standing| standing.place == 1)
+            .map(|standing| standing.player)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Error};
+
+    fn player(score: u32, reserve: &[Color]) -> Result<Player, Error> {
+        let mut player = Player::new();
+        player.score = score;
+        for color in reserve.iter() {
+            player.reserve.add(*color)?;
+        }
+        Ok(player)
+    }
+
+    #[test]
+    fn result_ranking() -> Result<(), Error> {
+        let result = GameResult::new(&[
+            player(12, &[])?,
+            player(20, &[])?,
+            player(12, &[Color::Red])?,
+            player(3, &[Color::Red, Color::Blue])?,
+        ]);
+        let order: Vec<(usize, usize)> = result
+            .standings()
+            .iter()
+            .map(|standing| (standing.player, standing.place))
+            .collect();
+        assert_eq!(order, vec![(1, 1), (2, 2), (0, 3), (3, 4)]);
+        assert_eq!(result.winners(), vec![1]);
+        assert_eq!(result.standing(2).map(|standing| standing.reserve), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn result_shared_placing() -> Result<(), Error> {
+        let result = GameResult::new(&[
+            player(7, &[Color::Green])?,
+            player(9, &[])?,
+            player(9, &[])?,
+            player(7, &[Color::Yellow])?,
+        ]);
+        let places: Vec<usize> = (0..4)
+            .map(|player| result.standing(player).unwrap().place)
+            .collect();
+        assert_eq!(places, vec![3, 1, 1, 3]);
+        assert_eq!(result.winners(), vec![1, 2]);
+        assert_eq!(result.standings()[0].player, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Serde support, enabled by the `serde` feature.
+//!
+//! Every type is serialized as a string in its compact notation, so
+//! `Position::j3` becomes `"j3"` and a board becomes `"b1j2 r1j3 y1k2 g1k3"`.
+//! Boards are written in layered notation, keeping the colours beneath the
+//! tops. A game is serialized as a `deck` of the cards in hands and piles,
+//! written in full, and its `state` in the game state notation, see
+//! `Game::parse_with_deck`.
+use std::convert::TryFrom;
+use std::fmt;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use crate::notation::Mode;
+use crate::{Action, Board, Card, Color, Game, Hand, Move, Position, Reserve, Stack, Supply, Tier};
+
+macro_rules! serialize_notation {
+    ($($model:ty),* $(,)?) => {$(
+        impl Serialize for $model {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $model {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let notation = String::deserialize(deserializer)?;
+                <$model>::try_from(notation.as_str()).map_err(de::Error::custom)
+            }
+        }
+    )*};
+}
+
+serialize_notation!(Position, Color, Tier, Stack, Card, Reserve, Supply, Action, Move);
+
+/// A hand is written as its cards in full, since card references only mean
+/// something within the deck of a game.
+impl Serialize for Hand {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.cards().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Hand {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut hand = Hand::new();
+        for card in Vec::<Card>::deserialize(deserializer)? {
+            hand.add(card).map_err(de::Error::custom)?;
+        }
+        Ok(hand)
+    }
+}
+
+impl Serialize for Board {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_layered_notation())
+    }
+}
+
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let notation = String::deserialize(deserializer)?;
+        Board::try_from(notation.as_str()).map_err(de::Error::custom)
+    }
+}
+
+/// A game is written as the cards in hands and piles together with the game
+/// state notation, so that games played with any deck can be read back.
+impl Serialize for Game {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut deck: Vec<&Card> = self.players()
+            .iter()
+            .flat_map(|player| player.hand().cards())
+            .chain((0..self.display().piles()).flat_map(|pile| self.display().cards(pile)))
+            .collect();
+        deck.sort_by_key(|card| card.id());
+        let mut game = serializer.serialize_struct("Game", 2)?;
+        game.serialize_field("deck", &deck)?;
+        game.serialize_field("state", &self.to_notation())?;
+        game.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Game {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Game", &GAME_FIELDS, GameVisitor)
+    }
+}
+
+const GAME_FIELDS: [&str; 2] = ["deck", "state"];
+
+/// Game given as its deck and state, either by name or in that order.
+struct GameVisitor;
+
+impl GameVisitor {
+    fn game<E: de::Error>(deck: Vec<Card>, state: String) -> Result<Game, E> {
+        Game::parse_with_deck(&state, &deck, Mode::Strict).map_err(|diagnostics| de::Error::custom(diagnostics[0].to_error(&state)))
+    }
+}
+
+impl<'de> Visitor<'de> for GameVisitor {
+    type Value = Game;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a game with its deck and state")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Game, A::Error> {
+        let deck = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let state = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        GameVisitor::game(deck, state)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Game, A::Error> {
+        let (mut deck, mut state) = (None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "deck" if deck.is_none() => deck = Some(map.next_value()?),
+                "state" if state.is_none() => state = Some(map.next_value()?),
+                "deck" | "state" => return Err(de::Error::custom(format!("duplicate field `{}`", key))),
+                _ => return Err(de::Error::unknown_field(&key, &GAME_FIELDS)),
+            }
+        }
+        let deck = deck.ok_or_else(|| de::Error::missing_field("deck"))?;
+        let state = state.ok_or_else(|| de::Error::missing_field("state"))?;
+        GameVisitor::game(deck, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_deck, Error};
+
+    #[test]
+    fn serialize_notation() -> Result<(), Error> {
+        assert_eq!(serde_json::to_string(&Position::j3).unwrap(), "\"j3\"");
+        assert_eq!(serde_json::to_string(&Color::Blue).unwrap(), "\"b\"");
+        assert_eq!(serde_json::to_string(&Tier::Fourth).unwrap(), "\"4\"");
+        let board = Board::try_from("b1j2 r1j3 y1k2 g1k3")?;
+        let json = serde_json::to_string(&board).unwrap();
+        assert_eq!(json, "\"b1j2 r1j3 y1k2 g1k3\"");
+        assert_eq!(serde_json::from_str::<Board>(&json).unwrap(), board);
+        let layered = Board::try_from("rgb3j3 y1k2")?;
+        let json = serde_json::to_string(&layered).unwrap();
+        assert_eq!(json, "\"rgb3j3 y1k2\"");
+        let restored = serde_json::from_str::<Board>(&json).unwrap();
+        assert_eq!(restored.layers(&Position::j3), Some(vec![Color::Red, Color::Green, Color::Blue]));
+        let stacks: Vec<Stack> = serde_json::from_str("[\"r1\", \"g4\"]").unwrap();
+        assert_eq!(stacks, vec![Stack::try_from("r1")?, Stack::try_from("g4")?]);
+        let action = Action::try_from("T 2 -rb")?;
+        assert_eq!(serde_json::to_string(&action).unwrap(), "\"T 2 -rb\"");
+        let card = crate::placeholder_deck()[16].clone();
+        assert_eq!(serde_json::to_string(&card).unwrap(), "\"c17 AdjacentT2(b) 2 bg\"");
+        assert_eq!(serde_json::from_str::<Card>("\"c17 AdjacentT2(b) 2 bg\"").unwrap(), card);
+        let mut hand = Hand::new();
+        hand.add(Card::try_from("c60 Line(r) 2 rg")?)?;
+        let json = serde_json::to_string(&hand).unwrap();
+        assert_eq!(json, "[\"c60 Line(r) 2 rg\"]");
+        assert_eq!(serde_json::from_str::<Hand>(&json).unwrap(), hand);
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_game() -> Result<(), Error> {
+        let mut game = Game::new(2, 3)?;
+        let action = game.legal_actions()[0].clone();
+        game.apply(action)?;
+        let json = serde_json::to_string(&game).unwrap();
+        let restored: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, game);
+        assert_eq!(restored.display(), game.display());
+        assert!(json.starts_with("{\"deck\":[\"c1 Line(r) 2 rg\","));
+        assert!(json.ends_with(&format!("\"state\":\"{}\"}}", game.to_notation())));
+        assert!(serde_json::from_str::<Game>("[71, 1, 9]").is_err());
+        assert!(serde_json::from_str::<Game>("{\"deck\": []}").is_err());
+        let deck = parse_deck("c60 Line(r) 2 rg\nc61 Surround(y,b) 5 ry\nc62 Corner(g) 3 gg\n")?;
+        let custom = Game::parse_with_deck("-:rg:c60:0/-:-:-:0 2 c61/c62 r25g25b26y26 -", &deck, Mode::Strict).unwrap();
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(json, "{\"deck\":[\"c60 Line(r) 2 rg\",\"c61 Surround(y,b) 5 ry\",\"c62 Corner(g) 3 gg\"],\
+            \"state\":\"-:rg:c60:0/-:-:-:0 2 c61/c62 r25g25b26y26 -\"}");
+        assert_eq!(serde_json::from_str::<Game>(&json).unwrap(), custom);
+        let missing = json.replace("\"c61 Surround(y,b) 5 ry\",", "");
+        assert!(serde_json::from_str::<Game>(&missing).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_error() {
+        let error = serde_json::from_str::<Position>("\"z9\"").unwrap_err();
+        assert_eq!(error.to_string(), "Failed to parse notation 'z9' at offset 0");
+        assert!(serde_json::from_str::<Board>("3").is_err());
+    }
+}
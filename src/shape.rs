@@ -0,0 +1,424 @@
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+use crate::{Board, Diagnostic, Mask, MaskSet, Mode, Pattern, Position, Token, tokenize};
+use crate::predicate::{parse_predicate, Predicate};
+
+/// Parse a shape drawn as a grid of cell conditions, see `parse_predicate`,
+/// with rows from the top of the board down and `.` for positions outside the
+/// shape.
+///
+/// Cells are separated by whitespace or commas. Lines of flags may precede
+/// or follow the grid: `fixed` keeps the shape in the orientation drawn and
+/// `mirror` lets it fit in mirror images too. Every problem found is reported
+/// with the span of the offending cell or flag.
+///
+/// ```text
+/// r2+ .  .
+/// ?   r  r
+/// mirror
+/// ```
+pub fn parse_shape_grid(definition: &str) -> Result<ShapePattern, Vec<Diagnostic>> {
+    let mut rows: Vec<Vec<Token>> = Vec::new();
+    let mut diagnostics = Vec::new();
+    let (mut fixed, mut mirror) = (false, false);
+    let mut start = 0;
+    for line in definition.split('\n') {
+        let (mut tokens, found) = tokenize(line, Mode::Lenient);
+        for token in tokens.iter_mut() {
+            token.span = start + token.span.start..start + token.span.end;
+        }
+        diagnostics.extend(found.into_iter().map(|diagnostic| diagnostic.shifted(start)));
+        start += line.len() + 1;
+        match tokens.first().map(|token| token.text) {
+            None => {},
+            Some("fixed") | Some("mirror") => {
+                for token in tokens {
+                    match token.text {
+                        "fixed" => fixed = true,
+                        "mirror" => mirror = true,
+                        text => {
+                            let message = format!("unknown flag '{}', expected fixed or mirror", text);
+                            diagnostics.push(Diagnostic::new(token.span, message));
+                        },
+                    }
+                }
+            },
+            Some(_) => rows.push(tokens),
+        }
+    }
+    let mut cells: Vec<(Position, Arc<dyn Predicate + Send + Sync>)> = Vec::new();
+    for (row, tokens) in rows.iter().enumerate() {
+        for (column, token) in tokens.iter().enumerate() {
+            if token.text == "." {
+                continue;
+            }
+            let predicate = match parse_predicate(token.text) {
+                Ok(predicate) => predicate,
+                Err(_) => {
+                    let message = format!(
+                        "unknown cell '{}', expected a cell condition such as r, ?2+ or [rg]&!3, or .",
+                        token.text,
+                    );
+                    diagnostics.push(Diagnostic::new(token.span.clone(), message));
+                    continue;
+                },
+            };
+            match Position::from_coords(column as u8 + 1, (rows.len() - row) as u8) {
+                Ok(position) => cells.push((position, predicate)),
+                Err(_) => {
+                    let message = "cell is outside the board, shapes are at most 4 by 4".to_string();
+                    diagnostics.push(Diagnostic::new(token.span.clone(), message));
+                },
+            }
+        }
+    }
+    if cells.is_empty() && diagnostics.is_empty() {
+        diagnostics.push(Diagnostic::new(0..definition.len(), "expected at least one cell".to_string()));
+    }
+    if !diagnostics.is_empty() {
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        return Err(diagnostics);
+    }
+    let mut shape = ShapePattern::new(cells);
+    if fixed {
+        shape = shape.fixed();
+    }
+    if mirror {
+        shape = shape.reflected();
+    }
+    Ok(shape)
+}
+
+/// Offset of a cell from the corner of a shape, by file and by rank.
+type Offset = (i8, i8);
+
+/// Rotations by a quarter turn each, starting with the shape as given.
+const ROTATIONS: [fn(Offset) -> Offset; 4] = [
+    |(x, y)| (x, y),
+    |(x, y)| (-y, x),
+    |(x, y)| (-x, -y),
+    |(x, y)| (y, -x),
+];
+
+/// Pattern of cells in a fixed arrangement, each with its own condition.
+///
+/// The shape is drawn once with its cells at any positions of the board and
+/// fits in all its distinct rotations, and in their mirror images as well
+/// when reflections are allowed. Rotations that look the same, like a line
+/// turned upside down, are tried only once.
+#[derive(Clone)]
+pub struct ShapePattern {
+    cells: Vec<(Position, Arc<dyn Predicate + Send + Sync>)>,
+    rotations: bool,
+    reflections: bool,
+    /// Cells of every distinct orientation as offsets from its lower left
+    /// corner, each with the index of its condition.
+    orientations: Vec<Vec<(Offset, usize)>>,
+    /// Index of the first cell of every distinct condition.
+    conditions: Vec<usize>,
+    /// Every placement of every orientation on the board as a 16-bit board
+    /// mask, together with the cells of every distinct condition.
+    placements: Vec<(u16, Vec<u16>)>,
+}
+
+impl ShapePattern {
+    /// Shape of the given cells. A shape without cells fits nowhere.
+    pub fn new(cells: Vec<(Position, Arc<dyn Predicate + Send + Sync>)>) -> ShapePattern {
+        let mut shape = ShapePattern {
+            cells,
+            rotations: true,
+            reflections: false,
+            orientations: Vec::new(),
+            conditions: Vec::new(),
+            placements: Vec::new(),
+        };
+        shape.orient();
+        shape
+    }
+
+    /// Shape of cells sharing the same condition, e.g. a line of red stacks.
+    pub fn uniform(predicate: Arc<dyn Predicate + Send + Sync>, positions: &[Position]) -> ShapePattern {
+        ShapePattern::new(positions.iter().map(|position| (*position, predicate.clone())).collect())
+    }
+
+    /// Keep the shape in the orientation it is drawn in.
+    pub fn fixed(mut self) -> ShapePattern {
+        self.rotations = false;
+        self.orient();
+        self
+    }
+
+    /// Let the shape fit in mirror images as well.
+    pub fn reflected(mut self) -> ShapePattern {
+        self.reflections = true;
+        self.orient();
+        self
+    }
+
+    pub fn cells(&self) -> &[(Position, Arc<dyn Predicate + Send + Sync>)] {
+        &self.cells
+    }
+
+    pub fn rotations(&self) -> bool {
+        self.rotations
+    }
+
+    pub fn reflections(&self) -> bool {
+        self.reflections
+    }
+
+    /// Number of distinct orientations the shape fits in.
+    pub fn orientations(&self) -> usize {
+        self.orientations.len()
+    }
+
+    fn orient(&mut self) {
+        let mirrors: &[bool] = if self.reflections { &[false, true] } else { &[false] };
+        let rotations = if self.rotations { &ROTATIONS[..] } else { &ROTATIONS[..1] };
+        let mut orientations: Vec<Vec<(Offset, usize)>> = Vec::new();
+        let mut seen: Vec<Vec<(Offset, String)>> = Vec::new();
+        for mirror in mirrors {
+            for rotate in rotations {
+                let mut cells: Vec<(Offset, usize)> = self.cells
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (position, _))| {
+                        let (x, y) = position.to_coords();
+                        let x = if *mirror { -(x as i8) } else { x as i8 };
+                        (rotate((x, y as i8)), index)
+                    })
+                    .collect();
+                let left = cells.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+                let bottom = cells.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+                for ((x, y), _) in cells.iter_mut() {
+                    *x -= left;
+                    *y -= bottom;
+                }
+                cells.sort_unstable();
+                let mut look: Vec<(Offset, String)> = cells
+                    .iter()
+                    .map(|(offset, index)| (*offset, self.cells[*index].1.to_notation()))
+                    .collect();
+                look.sort();
+                if !seen.contains(&look) {
+                    seen.push(look);
+                    orientations.push(cells);
+                }
+            }
+        }
+        self.orientations = orientations;
+        self.place();
+    }
+
+    /// Work out every placement of every orientation, so fitting takes
+    /// only a comparison of masks per placement.
+    fn place(&mut self) {
+        let notations: Vec<String> = self.cells.iter().map(|(_, predicate)| predicate.to_notation()).collect();
+        let condition: Vec<usize> = notations
+            .iter()
+            .map(|notation| notations.iter().position(|other| other == notation).expect("notation is listed"))
+            .collect();
+        self.conditions = (0..self.cells.len()).filter(|index| condition[*index] == *index).collect();
+        let group = |index: usize| self.conditions.iter().position(|first| *first == condition[index]);
+        let mut placements = Vec::new();
+        for orientation in self.orientations.iter() {
+            for corner in Position::iter() {
+                let (x, y) = corner.to_coords();
+                let mut mask = 0;
+                let mut parts = vec![0; self.conditions.len()];
+                let placed = orientation.iter().all(|((dx, dy), index)| {
+                    match Position::from_coords(x + *dx as u8, y + *dy as u8) {
+                        Ok(position) => {
+                            mask |= position.bit();
+                            parts[group(*index).expect("condition is listed")] |= position.bit();
+                            true
+                        },
+                        Err(_) => false,
+                    }
+                });
+                if placed && mask != 0 {
+                    placements.push((mask, parts));
+                }
+            }
+        }
+        self.placements = placements;
+    }
+
+    /// Every placement of the shape on the board as a 16-bit board mask,
+    /// regardless of the conditions of its cells.
+    pub fn placement_masks(&self) -> Vec<u16> {
+        let mut masks: Vec<u16> = self.placements.iter().map(|(mask, _)| *mask).collect();
+        masks.sort_unstable();
+        masks.dedup();
+        masks
+    }
+}
+
+impl Pattern for ShapePattern {
+    /// Conditions and positions of the cells as drawn, followed by `fixed`
+    /// without rotations and `mirror` with reflections, e.g.
+    /// `Shape(r@i1,r@i2,g@j1)`.
+    fn to_notation(&self) -> String {
+        let mut arguments: Vec<String> = self.cells
+            .iter()
+            .map(|(position, predicate)| format!("{}@{}", predicate.to_notation(), position))
+            .collect();
+        if !self.rotations {
+            arguments.push("fixed".to_string());
+        }
+        if self.reflections {
+            arguments.push("mirror".to_string());
+        }
+        format!("Shape({})", arguments.join(","))
+    }
+
+    fn fit(&self, board: &Board) -> MaskSet {
+        let mut fits = MaskSet::new();
+        if self.cells.is_empty() {
+            return fits;
+        }
+        for orientation in self.orientations.iter() {
+            for corner in Position::iter() {
+                let (x, y) = corner.to_coords();
+                let mask: Option<Mask> = orientation
+                    .iter()
+                    .map(|((dx, dy), index)| {
+                        let position = Position::from_coords(x + *dx as u8, y + *dy as u8).ok()?;
+                        Some(position).filter(|position| self.cells[*index].1.test(board.get(position)))
+                    })
+                    .collect();
+                if let Some(mask) = mask {
+                    fits.insert(mask);
+                }
+            }
+        }
+        fits
+    }
+
+    fn fit_bits(&self, board: &Board) -> Vec<u16> {
+        let masks: Vec<u16> = self.conditions.iter().map(|index| self.cells[*index].1.mask(board)).collect();
+        let mut fits: Vec<u16> = self.placements
+            .iter()
+            .filter(|(_, parts)| parts.iter().zip(masks.iter()).all(|(cells, mask)| mask & cells == *cells))
+            .map(|(placement, _)| *placement)
+            .collect();
+        fits.sort_unstable();
+        fits.dedup();
+        fits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Card, Color, Error};
+
+    #[test]
+    fn shape_orientations() {
+        let red: Arc<dyn Predicate + Send + Sync> = Arc::new(Color::Red);
+        let shape = |positions: &[Position]| ShapePattern::uniform(red.clone(), positions);
+        assert_eq!(shape(&[Position::i1, Position::j1, Position::k1]).orientations(), 2);
+        assert_eq!(shape(&[Position::i1, Position::j1, Position::i2, Position::j2]).orientations(), 1);
+        assert_eq!(shape(&[Position::i1, Position::i2, Position::j2]).orientations(), 4);
+        let l = shape(&[Position::i1, Position::i2, Position::i3, Position::j1]);
+        assert_eq!(l.orientations(), 4);
+        assert_eq!(l.reflected().orientations(), 8);
+        let s = shape(&[Position::i1, Position::j1, Position::j2, Position::k2]);
+        assert_eq!(s.orientations(), 2);
+        assert_eq!(s.reflected().orientations(), 4);
+        let mixed = ShapePattern::new(vec![(Position::j3, red.clone()), (Position::k3, Arc::new(Color::Green))]);
+        assert_eq!(mixed.orientations(), 4);
+    }
+
+    #[test]
+    fn shape_fit() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4 r1
+            3 r2 g1
+            2 r1 r3 r1
+            1 y1 b1 r2
+        ")?;
+        let t = ShapePattern::uniform(Arc::new(Color::Red), &[Position::i2, Position::j2, Position::k2, Position::j1]);
+        assert_eq!(t.fit(&board).len(), 0);
+        let l = ShapePattern::uniform(Arc::new(Color::Red), &[Position::i1, Position::i2, Position::i3, Position::j1]);
+        assert_eq!(l.fit(&board), btreeset!{
+            btreeset!{Position::i2, Position::i3, Position::i4, Position::j2},
+        });
+        assert_eq!(l.reflected().fit(&board), btreeset!{
+            btreeset!{Position::i2, Position::i3, Position::i4, Position::j2},
+            btreeset!{Position::i2, Position::j2, Position::k2, Position::k1},
+            btreeset!{Position::i2, Position::j2, Position::k2, Position::i3},
+        });
+        let cells: Vec<(Position, Arc<dyn Predicate + Send + Sync>)> = vec![
+            (Position::i1, parse_predicate("r")?),
+            (Position::j1, parse_predicate("3")?),
+        ];
+        let shape = ShapePattern::new(cells);
+        assert_eq!(shape.fit(&board), btreeset!{
+            btreeset!{Position::i2, Position::j2},
+            btreeset!{Position::k2, Position::j2},
+        });
+        assert_eq!(shape.to_notation(), "Shape(r@i1,3@j1)");
+        Ok(())
+    }
+
+    #[test]
+    fn shape_empty() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4
+            3
+            2 r1
+            1 r2 g1
+        ")?;
+        let empty = ShapePattern::new(Vec::new()).reflected();
+        assert!(empty.fit(&board).is_empty());
+        assert!(empty.fit_bits(&board).is_empty());
+        assert_eq!(empty.count(&board), 0);
+        assert!(empty.placement_masks().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn shape_grid() -> Result<(), Error> {
+        let board = Board::from_grid("
+            4 r1
+            3 r2 g1
+            2 r1 r3 r1
+            1 y1 b1 r2
+        ")?;
+        let pattern = parse_shape_grid("
+            r2+ .
+            r   r
+        ").unwrap();
+        assert_eq!(pattern.to_notation(), "Shape(r2+@i2,r@i1,r@j1)");
+        assert_eq!(pattern.fit(&board), btreeset!{
+            btreeset!{Position::i3, Position::i2, Position::j2},
+            btreeset!{Position::j2, Position::k2, Position::k1},
+        });
+        let fixed = parse_shape_grid("r2+ .\nr r\nfixed").unwrap();
+        assert_eq!(fixed.to_notation(), "Shape(r2+@i2,r@i1,r@j1,fixed)");
+        assert_eq!(fixed.fit(&board), btreeset!{btreeset!{Position::i3, Position::i2, Position::j2}});
+        let l = parse_shape_grid("fixed mirror\nr .\nr .\nr r").unwrap();
+        assert_eq!(l.fit(&board), btreeset!{btreeset!{Position::i2, Position::i3, Position::i4, Position::j2}});
+        let card = Card::new(60, Arc::new(pattern), 2, [Color::Red, Color::Red]);
+        assert_eq!(card.to_notation(), "c60 Shape(r2+@i2,r@i1,r@j1) 2 rr");
+        assert_eq!(card.score(&board), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn shape_grid_errors() {
+        let messages = |definition| -> Vec<String> {
+            let diagnostics = parse_shape_grid(definition).err().unwrap();
+            diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect()
+        };
+        assert_eq!(messages("r x2 .\n? r5+\nmirror flip"), vec![
+            "2..4: unknown cell 'x2', expected a cell condition such as r, ?2+ or [rg]&!3, or .",
+            "9..12: unknown cell 'r5+', expected a cell condition such as r, ?2+ or [rg]&!3, or .",
+            "20..24: unknown flag 'flip', expected fixed or mirror",
+        ]);
+        assert_eq!(messages("r r r r r"), vec!["8..9: cell is outside the board, shapes are at most 4 by 4"]);
+        assert_eq!(messages(" . .\nmirror"), vec!["0..11: expected at least one cell"]);
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use strum::IntoEnumIterator;
+use crate::{Color, Error, Reason};
+
+/// Number of pieces of every colour in the game box.
+pub const PIECES_PER_COLOR: u8 = 28;
+
+/// Shared pool of pieces players collect into their reserves.
+///
+/// Pieces only come back when discarded from a reserve. Once placed on a
+/// board, they stay there for the rest of the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Supply {
+    pieces: HashMap<Color, u8>,
+}
+
+impl Supply {
+    /// Create a supply with the same number of pieces of every colour.
+    pub fn new(count: u8) -> Supply {
+        Supply {
+            pieces: Color::iter().map(|color| (color, count)).collect(),
+        }
+    }
+
+    /// Number of pieces of the given colour.
+    pub fn count(&self, color: Color) -> u8 {
+        self.pieces.get(&color).copied().unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.values().map(|count| *count as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// First colour that has run out, if any.
+    pub fn exhausted(&self) -> Option<Color> {
+        Color::iter().find(|color| self.count(*color) == 0)
+    }
+
+    pub fn take(&mut self, color: Color) -> Result<(), Error> {
+        match self.pieces.get_mut(&color) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                Ok(())
+            },
+            _ => Err(Error::rule(Reason::SupplyEmpty)),
+        }
+    }
+
+    /// Return a piece to the supply.
+    pub fn put(&mut self, color: Color) {
+        *self.pieces.entry(color).or_insert(0) += 1;
+    }
+}
+
+/// Count of every colour, e.g. `r26g26b24y25`.
+impl fmt::Display for Supply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for color in Color::iter() {
+            write!(f, "{}{}", color, self.count(color))?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for Supply {
+    type Error = Error;
+
+    fn try_from(notation: &str) -> Result<Self, Self::Error> {
+        let mut supply = Supply::new(0);
+        let mut rest = notation;
+        for color in Color::iter() {
+            let offset = notation.len() - rest.len();
+            let digits = rest.strip_prefix(color.to_notation().as_str())
+                .ok_or_else(|| Error::notation(rest, offset))?;
+            let length = digits.bytes().take_while(|digit| digit.is_ascii_digit()).count();
+            let count = digits[..length].parse().map_err(|_| Error::notation(&rest[..length + 1], offset))?;
+            supply.pieces.insert(color, count);
+            rest = &digits[length..];
+        }
+        if !rest.is_empty() {
+            return Err(Error::notation(rest, notation.len() - rest.len()));
+        }
+        Ok(supply)
+    }
+}
+
+impl Default for Supply {
+    fn default() -> Self {
+        Supply::new(PIECES_PER_COLOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supply_pieces() -> Result<(), Error> {
+        let mut supply = Supply::new(1);
+        assert_eq!(supply.len(), 4);
+        assert_eq!(supply.exhausted(), None);
+        supply.take(Color::Blue)?;
+        assert!(supply.take(Color::Blue).is_err());
+        assert_eq!(supply.count(Color::Blue), 0);
+        assert_eq!(supply.exhausted(), Some(Color::Blue));
+        supply.put(Color::Blue);
+        assert_eq!(supply.exhausted(), None);
+        assert_eq!(Supply::default().count(Color::Red), PIECES_PER_COLOR);
+        Ok(())
+    }
+
+    #[test]
+    fn supply_notation() -> Result<(), Error> {
+        let supply = Supply::try_from("r26g0b24y25")?;
+        assert_eq!(supply.count(Color::Green), 0);
+        assert_eq!(supply.count(Color::Yellow), 25);
+        assert_eq!(supply.to_string(), "r26g0b24y25");
+        assert!(matches!(Supply::try_from("r26b24g0y25").unwrap_err(), Error::InvalidNotation { token, offset: 3 } if token == "b24g0y25"));
+        assert!(matches!(Supply::try_from("r26g0b24y25y").unwrap_err(), Error::InvalidNotation { token, offset: 11 } if token == "y"));
+        assert!(Supply::try_from("r26gb24y25").is_err());
+        assert!(Supply::try_from("r300g0b24y25").is_err());
+        Ok(())
+    }
+}